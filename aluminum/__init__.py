@@ -1,6 +1,9 @@
 from aluminum.base import Base
 from aluminum.bucket import Bucket
-from aluminum.engine import Engine, create_engine
+from aluminum.engine import Engine, RetryPolicy, create_engine
 from aluminum.store import Store
 from aluminum.aluminum import get_schema
 from aluminum.mapped_column import Mapped, mapped_column
+from aluminum.role import Role
+from aluminum.types import UInt
+from aluminum.writer import BufferedWriter
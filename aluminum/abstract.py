@@ -1,5 +1,6 @@
 from abc import ABC, abstractclassmethod, abstractmethod
-from typing import Any, Generic, Self, Type, TypeVar
+from datetime import datetime, timedelta
+from typing import Any, AsyncIterator, Callable, Generic, Optional, Self, Type, TypeVar, Union
 
 from aluminum.operator import WhereOperator
 
@@ -71,27 +72,106 @@ class AbstractSelect(ABC):
     def where(self, *args: AbstractWhereClause[Any]) -> Self:
         ...
 
-    def _where(
+    def _where(self, clause: AbstractWhereClause[Any]) -> None:
+        ...
+
+    def range(
         self,
-        left_operand: AbstractMapped[Any],
-        operator: WhereOperator,
-        right_operand: Any,
+        start: Optional[Union[datetime, timedelta, str]] = None,
+        stop: Optional[Union[datetime, timedelta, str]] = None,
     ) -> Self:
         ...
 
-    def _create_bucket_str(self, name: str) -> None:
+    def group_by(self, *columns: AbstractMapped[Any]) -> Self:
+        ...
+
+    def aggregate_window(
+        self,
+        every: Union[timedelta, str] = "1m",
+        fn: str = "mean",
+        create_empty: bool = False,
+    ) -> Self:
+        ...
+
+    def derivative(
+        self, unit: Union[timedelta, str] = "1s", non_negative: bool = False
+    ) -> Self:
+        ...
+
+    def non_negative_derivative(self, unit: Union[timedelta, str] = "1s") -> Self:
+        ...
+
+    def difference(self, non_negative: bool = False) -> Self:
+        ...
+
+    def moving_average(self, n: int) -> Self:
+        ...
+
+    def exponential_moving_average(self, n: int) -> Self:
+        ...
+
+    def cumulative_sum(self) -> Self:
+        ...
+
+    def elapsed(
+        self, unit: Union[timedelta, str] = "1s", column_name: str = "elapsed"
+    ) -> Self:
+        ...
+
+    def order_by(self, *columns: Any) -> Self:
+        ...
+
+    def limit(self, n: int) -> Self:
+        ...
+
+    def offset(self, n: int) -> Self:
+        ...
+
+    def first(self) -> Self:
+        ...
+
+    def last(self) -> Self:
+        ...
+
+    def to_flux(self) -> str:
         ...
 
-    def _create_filter_str(self) -> None:
+
+class AbstractBufferedWriter(ABC):
+    @abstractmethod
+    def add(self, item: AbstractBase) -> None:
         ...
 
-    def _create_range_str(self) -> None:
+    @abstractmethod
+    def pending(self) -> int:
         ...
 
-    def _create_raw_query(self) -> str:
+    @abstractmethod
+    async def flush(self) -> int:
         ...
 
-    def _get_raw_query(self) -> str:
+    @abstractmethod
+    async def close(self) -> int:
+        ...
+
+
+class AbstractQueryStream(ABC):
+    @abstractmethod
+    def batches(self) -> AsyncIterator[list[AbstractBase]]:
+        ...
+
+    @abstractmethod
+    async def close(self) -> None:
+        ...
+
+
+class AbstractArrowResult(ABC):
+    @abstractmethod
+    def __arrow_c_stream__(self, requested_schema: Optional[object] = None) -> object:
+        ...
+
+    @abstractmethod
+    def to_pyarrow(self) -> Any:
         ...
 
 
@@ -100,6 +180,22 @@ class AbstractBucket(ABC):
     async def add(self, item: AbstractBase) -> None:
         ...
 
+    @abstractmethod
+    async def add_all(
+        self, items: list[AbstractBase], max_points: int, max_bytes: int
+    ) -> dict:
+        ...
+
+    @abstractmethod
+    def writer(
+        self,
+        max_points: int,
+        max_bytes: int,
+        flush_interval: float,
+        on_error: Optional[Callable[[ConnectionError, list[str]], None]],
+    ) -> AbstractBufferedWriter:
+        ...
+
     @abstractmethod
     def to_dict(self) -> dict:
         ...
@@ -113,7 +209,22 @@ class AbstractBucket(ABC):
         ...
 
     @abstractmethod
-    async def execute(self, select: AbstractSelect) -> list[AbstractBase]:
+    def stream(self, select: AbstractSelect) -> AbstractQueryStream:
+        ...
+
+    @abstractmethod
+    async def execute(
+        self, select: AbstractSelect
+    ) -> Union[
+        None,
+        AbstractBase,
+        list[AbstractBase],
+        dict[tuple[Any, ...], Any],
+    ]:
+        ...
+
+    @abstractmethod
+    async def execute_arrow(self, select: AbstractSelect) -> AbstractArrowResult:
         ...
 
 
@@ -1,8 +1,10 @@
-from typing import Any, Type, TypeVar
+from datetime import datetime, timedelta
+from typing import Any, Callable, Iterable, Optional, Type, TypeVar, Union
 
 from aluminum.abstract import (
     AbstractBase,
     AbstractBucket,
+    AbstractBufferedWriter,
     AbstractMapped,
     AbstractRegistry,
     AbstractSelect,
@@ -26,6 +28,42 @@ class _Bucket(AbstractBucket):
         be of the same type as the model used to get this bucket.
 
         :param item: the model object to add
+        :raises TypeError: if a field value does not match its column type
+        :raises ValueError: if an int or UInt field value is out of range
+        :raises RuntimeError: if the server rejects the point
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    async def add_all(
+        self, items: list[Base], max_points: int = 5000, max_bytes: int = 10_000_000
+    ) -> dict:
+        """
+        Adds several Model instances to the bucket, split into requests of at
+        most max_points points and max_bytes bytes of line protocol.
+
+        :param items: the model objects to add
+        :param max_points: the maximum number of points per request
+        :param max_bytes: the maximum size of a request body in bytes
+        :return: a summary with the number of points written and requests sent
+        :raises RuntimeError: if the server rejects a request
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    def writer(
+        self,
+        max_points: int = 5000,
+        max_bytes: int = 10_000_000,
+        flush_interval: float = 1.0,
+        on_error: Optional[Callable[[ConnectionError, list[str]], None]] = None,
+    ) -> _BufferedWriter:
+        """
+        Creates a writer that buffers points and writes them in the background
+        every flush_interval seconds, or as soon as max_points are buffered.
+
+        :param max_points: the maximum number of points per request
+        :param max_bytes: the maximum size of a request body in bytes
+        :param flush_interval: the number of seconds between background flushes
+        :param on_error: called with the error and the line protocol of each failed batch.
+            Without it, the failure is raised by the next flush or close.
+        :return: the buffered writer
         """
     def to_dict(self) -> dict:
         """
@@ -41,9 +79,123 @@ class _Bucket(AbstractBucket):
         """
     async def raw_query(self, select: str) -> list[Base]:
         """
-        Queries the bucket using the given raw query.
+        Queries the bucket using the given raw query. Mapped columns are
+        converted to their declared types, missing columns are set to None.
+        The result also has "tables", each with its "result" name, "table"
+        number, "group_key" values by column name and the "data" of its rows,
+        and "results", the records of each result named by yield.
 
         :param select: the raw query to use for querying
+        :raises TypeError: if a field value does not match its column type
+        :raises RuntimeError: if the server rejects the query
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    async def execute(self, select: _Select) -> dict:
+        """
+        Queries the bucket with the Flux rendered by the select clause. Fields
+        aggregated by count are decoded as int, by mean or median as float.
+        Grouped selects also return a "groups" mapping from the tuple of group
+        values to the records of the group, and "single" is true when the
+        select expects a single record. "tables" and "results" are returned
+        as by raw_query.
+
+        :param select: the select clause to run
+        :raises TypeError: if a field value does not match its column type
+        :raises RuntimeError: if the server rejects the query
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    async def execute_arrow(self, select: _Select) -> _ArrowResult:
+        """
+        Queries the bucket with the Flux rendered by the select clause and
+        decodes the records into Arrow record batches, one column per selected
+        column typed by the schema. Group key columns are appended when they
+        are not selected.
+
+        :param select: the select clause to run
+        :raises TypeError: if a value does not match its column type, or a
+            column is a dict, list or tuple
+        :raises RuntimeError: if the server rejects the query
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    def stream(self, select: _Select) -> _QueryStream:
+        """
+        Streams the records of the select clause, decoding the response as it
+        arrives instead of all at once.
+
+        :param select: the select clause to run
+        :return: the stream of records, which sends the query when first read
+        :raises ValueError: if the select is grouped or keeps a single record
+        """
+
+class _QueryStream:
+    """
+    _QueryStream is an async iterator over the records of a query, decoded as
+    the response arrives.
+    """
+
+    def __aiter__(self) -> _QueryStream: ...
+    async def __anext__(self) -> list[dict]:
+        """
+        Reads the response until some rows are complete. The query is sent
+        on the first call.
+
+        :return: the records completed by the next chunks of the response
+        :raises TypeError: if a field value does not match its column type
+        :raises RuntimeError: if the server rejects the query
+        :raises ConnectionError: if the server can't be reached or fails
+        """
+    async def close(self) -> None:
+        """
+        Closes the response, ending the iteration.
+        """
+
+class _ArrowResult:
+    """
+    _ArrowResult holds the Arrow record batches of a query, exported through
+    the Arrow PyCapsule interface.
+    """
+
+    num_rows: int
+    column_names: list[str]
+
+    def __arrow_c_schema__(self) -> object:
+        """
+        Exports the schema of the batches as an "arrow_schema" capsule.
+        """
+    def __arrow_c_stream__(self, requested_schema: Optional[object] = None) -> object:
+        """
+        Exports the batches as an "arrow_array_stream" capsule. Each call
+        returns a new stream, the requested schema is ignored.
+        """
+
+class _BufferedWriter(AbstractBufferedWriter):
+    """
+    _BufferedWriter accumulates points and writes them to its bucket in the background.
+    """
+
+    def add(self, item: Base) -> None:
+        """
+        Buffers a single Model instance without waiting for it to be written.
+
+        :param item: the model object to add
+        """
+    def pending(self) -> int:
+        """
+        Returns the number of points waiting to be written.
+
+        :return: the number of buffered points
+        """
+    async def flush(self) -> int:
+        """
+        Writes every buffered point.
+
+        :return: the number of points written
+        """
+    async def close(self) -> int:
+        """
+        Stops the background flushes and writes every buffered point.
+
+        :return: the number of points written
         """
 
 class _Store(AbstractStore):
@@ -118,9 +270,76 @@ class _Mapped(AbstractMapped):
     _Mapped is a class that represents a mapped column in the database.
     """
 
-    def __new__(cls, col_name: str):
+    def __new__(cls, col_name: str, role: str = "field"):
         """
         Creates a new instance of _Mapped.
+
+        :param col_name: the column name
+        :param role: one of "measurement", "tag", "field" or "timestamp"
+        """
+    def in_(self, values: Iterable[Any]) -> _WhereClause:
+        """
+        Matches records whose column value is one of the values, each
+        checked against the type of the column.
+
+        :param values: a list, tuple or set of values
+        :raises TypeError: if values is a str
+        """
+    def not_in(self, values: Iterable[Any]) -> _WhereClause:
+        """
+        Matches records whose column value is none of the values.
+
+        :param values: a list, tuple or set of values
+        :raises TypeError: if values is a str
+        """
+    def is_(self, value: None) -> _WhereClause:
+        """
+        Matches records where the column is missing, same as `== None`.
+
+        :param value: must be None
+        :raises TypeError: if value is not None
+        """
+    def is_not(self, value: None) -> _WhereClause:
+        """
+        Matches records where the column is present, same as `!= None`.
+
+        :param value: must be None
+        :raises TypeError: if value is not None
+        """
+    def matches(self, pattern: str) -> _WhereClause:
+        """
+        Matches records whose column matches the regular expression, using
+        the RE2 syntax of Flux. Only applies to measurements, tags and str fields.
+
+        :param pattern: the regular expression
+        :raises ValueError: if the pattern is not a valid regular expression
+        """
+    def not_matches(self, pattern: str) -> _WhereClause:
+        """
+        Matches records whose column doesn't match the regular expression.
+
+        :param pattern: the regular expression
+        :raises ValueError: if the pattern is not a valid regular expression
+        """
+    def startswith(self, prefix: str) -> _WhereClause:
+        """
+        Matches records whose column starts with the prefix.
+        """
+    def endswith(self, suffix: str) -> _WhereClause:
+        """
+        Matches records whose column ends with the suffix.
+        """
+    def contains(self, substring: str) -> _WhereClause:
+        """
+        Matches records whose column contains the substring.
+        """
+    def asc(self) -> _Ordering:
+        """
+        Sorts by the column in ascending order.
+        """
+    def desc(self) -> _Ordering:
+        """
+        Sorts by the column in descending order.
         """
     def _get_col_name(self) -> str:
         """
@@ -128,6 +347,19 @@ class _Mapped(AbstractMapped):
 
         :return: the column name
         """
+    def _get_role(self) -> str:
+        """
+        Returns the role of the column.
+
+        :return: the role of the column
+        """
+
+class _Ordering:
+    """
+    _Ordering is a column and a sort direction, passed to order_by().
+    """
+
+    def __str__(self): ...
 
 class _WhereClause(AbstractWhereClause):
     """
@@ -143,9 +375,21 @@ class _WhereClause(AbstractWhereClause):
         """
         ...
     def __str__(self): ...
+    def __and__(self, other: _WhereClause) -> _WhereClause:
+        """
+        Combines two where clauses, both must match.
+        """
+    def __or__(self, other: _WhereClause) -> _WhereClause:
+        """
+        Combines two where clauses, at least one must match.
+        """
+    def __invert__(self) -> _WhereClause:
+        """
+        Negates the where clause.
+        """
     def get_left_operand(self) -> _Mapped:
         """
-        Returns the left operand of the where clause.
+        Returns the left operand of a single comparison.
 
         :return: the left operand of the where clause
         """
@@ -155,7 +399,7 @@ class _WhereClause(AbstractWhereClause):
 
         :return: the operator of the where clause
         """
-    def get_right_operand(self) -> str:
+    def get_right_operand(self) -> Any:
         """
         Returns the right operand of the where clause.
 
@@ -173,9 +417,12 @@ class _Select(AbstractSelect):
     _Select is a class that represents a select clause in the database.
     """
 
-    def __init__(self, select: AbstractBase):
+    def __init__(self, select: AbstractBase, columns: Optional[list[_Mapped]] = None):
         """
-        Creates a new instance of _Select.
+        Creates a new instance of _Select. With columns, the query keeps only
+        those columns and decodes records with only their keys.
+
+        :raises ValueError: if columns is empty or a column isn't mapped by the model
         """
     def where(self, *args: AbstractWhereClause[Any]) -> AbstractSelect: 
     """
@@ -185,43 +432,151 @@ class _Select(AbstractSelect):
     :return: the select clause
     """
     ...
-    def _where(
+    def _where(self, clause: _WhereClause) -> None:
+    """
+    Adds a where clause to the select clause, rendered as one filter() call.
+    The values of the clause are rendered as Flux literals, a str, int, float,
+    bool, datetime or timedelta checked against the type of their column.
+
+    :param clause: the where clause to add
+    :raises TypeError: if a value doesn't match the type of its column
+    :raises ValueError: if a column isn't mapped by the selected model
+    """
+    ...
+    def _range(
         self,
-        left_operand: AbstractMapped[Any],
-        operator: WhereOperator,
-        right_operand: Any,
-    ) -> AbstractSelect: 
+        start: Optional[Union[datetime, timedelta, str]] = None,
+        stop: Optional[Union[datetime, timedelta, str]] = None,
+    ) -> None:
     """
-    Adds a where clause to the select clause.
+    Sets the time range of the select clause. Durations and timedeltas are
+    relative to now, datetimes and ISO 8601 strings are absolute.
 
-    :param left_operand: the left operand of the where clause
-    :param operator: the operator of the where clause
-    :param right_operand: the right operand of the where clause
-    :return: the select clause
+    :param start: the start of the range, -1h by default
+    :param stop: the end of the range, now by default
+    :raises ValueError: if a string is not a duration or ISO 8601 time, or if start is not before stop
+    :raises TypeError: if a bound is not a datetime, timedelta or str
     """
     ...
-    def _create_range_str(self) -> None: 
+    def _group_by(self, columns: list[_Mapped]) -> None:
     """
-    Creates the range string for the select clause.
+    Groups the records by the given columns with group(), keeping fields apart.
+
+    :param columns: the tag or measurement columns of the group key
+    :raises TypeError: if a column is a field or the timestamp
+    :raises ValueError: if no column is given or a column isn't mapped by the selected model
     """
     ...
-    def _create_filter_str(self) -> None: 
+    def _aggregate_window(
+        self, every: Union[timedelta, str], function: str, create_empty: bool
+    ) -> None:
     """
-    Creates the filter string for the select clause.
+    Aggregates each series into windows with aggregateWindow(), stamping each
+    result with the stop time of its window. Numeric functions leave out the
+    fields that don't hold numbers, to_flux() raises a TypeError if such a
+    field is selected or if no field holds numbers.
+
+    :param every: the positive window duration
+    :param function: one of mean, sum, count, min, max, median, first or last
+    :param create_empty: whether windows without data produce a result
+    :raises ValueError: if the duration is not positive or the function is unknown
+    :raises TypeError: if every is not a timedelta or str
+    """
+    ...
+    def _derivative(self, unit: Union[timedelta, str], non_negative: bool) -> None:
+    """
+    Computes the rate of change per unit of each series with derivative().
+    Transformations apply after the where clauses, in the order they are
+    added, to every field of the model.
+
+    :param unit: the positive time unit of the rate
+    :param non_negative: whether negative rates are dropped
+    :raises ValueError: if the unit is not a positive fixed duration
+    :raises TypeError: if unit is not a timedelta or str
+    """
+    ...
+    def _difference(self, non_negative: bool) -> None:
+    """
+    Computes the difference between consecutive values with difference().
+
+    :param non_negative: whether negative differences are dropped
+    """
+    ...
+    def _moving_average(self, n: int) -> None:
+    """
+    Averages the last n values of each series with movingAverage().
+
+    :param n: the positive number of values
+    :raises ValueError: if n is not positive
+    """
+    ...
+    def _exponential_moving_average(self, n: int) -> None:
+    """
+    Averages each series with exponentialMovingAverage().
+
+    :param n: the positive number of values
+    :raises ValueError: if n is not positive
+    """
+    ...
+    def _cumulative_sum(self) -> None:
+    """
+    Computes the running total of each series with cumulativeSum().
+    """
+    ...
+    def _elapsed(self, unit: Union[timedelta, str], column_name: str) -> None:
+    """
+    Adds the time since the previous record with elapsed().
+
+    :param unit: the positive time unit of the elapsed time
+    :param column_name: the column name of an int field of the model
+    :raises ValueError: if the unit is not a positive fixed duration or the column isn't a mapped field
+    :raises TypeError: if unit is not a timedelta or str, or the field is not an int
+    """
+    ...
+    def _order_by(self, orderings: list[_Ordering]) -> None:
+    """
+    Sorts the records of each table with sort(). Flux sorts every column in
+    the same direction.
+
+    :param orderings: the columns to sort by
+    :raises ValueError: if no column is given, the directions differ, or a column isn't mapped by the selected model
+    """
+    ...
+    def _limit(self, n: int) -> None:
+    """
+    Keeps at most n records of each table with limit().
+
+    :raises ValueError: if n is negative
+    """
+    ...
+    def _offset(self, n: int) -> None:
+    """
+    Skips the first n records of each table, rendered in limit().
+
+    :raises ValueError: if n is negative
     """
     ...
-    def _create_bucket_str(self, name: str) -> None: 
+    def _first(self) -> None:
     """
-    Creates the bucket string for the select clause.
+    Limits the select to one record, or one per group, sorted by time unless
+    ordered, and decodes the result as a single record or None.
     """
     ...
-    def _create_raw_query(self) -> str:
+    def _last(self) -> None:
     """
-    Creates the raw query for the select clause.
+    Same as _first() with the sort order reversed.
     """
     ...
-    def _get_raw_query(self) -> str:
+    def to_flux(self) -> str:
     """
-    Returns the raw query for the select clause.
+    Renders the select clause as a Flux query: the source bucket, the range,
+    the filters, the grouping, the aggregation window, the sort, the limit,
+    then the selected columns. Models with several fields, and selects that
+    filter or sort on a field, are pivoted into one row per timestamp after
+    the aggregation window and transformations, or before the filters on
+    fields when there are some.
+
+    :raises ValueError: if an offset is given without a limit, or if filters
+        on fields are combined with an aggregation window or a moving average
     """
     ...
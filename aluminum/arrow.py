@@ -0,0 +1,41 @@
+from typing import Any, Optional
+
+from aluminum.abstract import AbstractArrowResult
+
+
+class ArrowResult(AbstractArrowResult):
+
+    _result: Any
+
+    def __init__(self, result: Any):
+        self._result = result
+
+    @property
+    def num_rows(self) -> int:
+        return self._result.num_rows
+
+    @property
+    def column_names(self) -> list[str]:
+        return self._result.column_names
+
+    def __len__(self) -> int:
+        return self.num_rows
+
+    def __arrow_c_schema__(self) -> object:
+        return self._result.__arrow_c_schema__()
+
+    def __arrow_c_stream__(self, requested_schema: Optional[object] = None) -> object:
+        return self._result.__arrow_c_stream__(requested_schema)
+
+    def to_pyarrow(self) -> Any:
+        import pyarrow
+
+        return pyarrow.table(self)
+
+    def to_pandas(self) -> Any:
+        return self.to_pyarrow().to_pandas()
+
+    def to_polars(self) -> Any:
+        import polars
+
+        return polars.DataFrame(self)
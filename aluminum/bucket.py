@@ -1,7 +1,12 @@
+from typing import Any, Callable, Optional, Type, Union
+
 from aluminum.abstract import AbstractBucket, AbstractSelect
+from aluminum.arrow import ArrowResult
 from aluminum.base import Base
-from aluminum.result import Result
+from aluminum.result import QueryResult, Result, Table
 from aluminum.select import Select
+from aluminum.stream import QueryStream
+from aluminum.writer import BufferedWriter
 
 
 class Bucket(AbstractBucket):
@@ -14,31 +19,81 @@ class Bucket(AbstractBucket):
     async def add(self, item: Base) -> None:
         return await self._bucket.add(item)
 
+    async def add_all(
+        self, items: list[Base], max_points: int = 5000, max_bytes: int = 10_000_000
+    ) -> dict:
+        return await self._bucket.add_all(items, max_points, max_bytes)
+
+    def writer(
+        self,
+        max_points: int = 5000,
+        max_bytes: int = 10_000_000,
+        flush_interval: float = 1.0,
+        on_error: Optional[Callable[[ConnectionError, list[str]], None]] = None,
+    ) -> BufferedWriter:
+        return BufferedWriter(
+            self._bucket.writer(max_points, max_bytes, flush_interval, on_error)
+        )
+
     def to_dict(self) -> dict:
         return self._bucket.to_dict()
 
     async def query(self, select: AbstractSelect) -> list[Base]:
         return await self._bucket.query(select)
 
-    async def raw_query(self, select: str) -> list[Base]:
+    async def raw_query(self, select: str) -> QueryResult[Base]:
         result = await self._bucket.raw_query(select)
         name = result["name"]
-        query_data = result["data"]
         cached_buckets = Base._get_collected_buckets()["buckets"]
         # TODO
         BucketClass = [bucket for bucket in cached_buckets if bucket.__name__ == name][
             0
         ]
-        return [BucketClass(**d) for d in query_data]
+        return _query_result(BucketClass, result)
 
-    async def execute(self, select: Select) -> list[Base]:
-        select._create_raw_query()
-        query = select._get_raw_query()
-        result = await self._bucket.raw_query(query)
+    async def execute(
+        self, select: Select
+    ) -> Union[None, Base, QueryResult[Base], dict[tuple[Any, ...], Any]]:
+        result = await self._bucket.execute(select._select)
         name = result["name"]
-        query_data = result["data"]
         cached_buckets = Base._get_collected_buckets()["buckets"]
         BucketClass = [bucket for bucket in cached_buckets if bucket.__name__ == name][
             0
         ]
-        return [BucketClass(**d) for d in query_data]
+
+        def build(data: list[dict]) -> Union[None, Base, list[Base]]:
+            models = [BucketClass(**d) for d in data]
+            if result["single"]:
+                return models[0] if models else None
+            return models
+
+        if "groups" in result:
+            return {key: build(group) for key, group in result["groups"].items()}
+        if result["single"]:
+            return build(result["data"])
+        return _query_result(BucketClass, result)
+
+    async def execute_arrow(self, select: Select) -> ArrowResult:
+        return ArrowResult(await self._bucket.execute_arrow(select._select))
+
+    def stream(self, select: Select) -> QueryStream:
+        return QueryStream(self._bucket.stream(select._select), select._select_bucket)
+
+
+def _query_result(model: Type[Base], result: dict) -> QueryResult[Base]:
+    results = {
+        name: [model(**d) for d in data] for name, data in result["results"].items()
+    }
+    return QueryResult(
+        [item for items in results.values() for item in items],
+        [
+            Table(
+                table["result"],
+                table["table"],
+                table["group_key"],
+                [model(**d) for d in table["data"]],
+            )
+            for table in result["tables"]
+        ],
+        results,
+    )
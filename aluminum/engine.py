@@ -1,22 +1,80 @@
+from typing import Optional
+
+
+class RetryPolicy:
+    max_attempts: int
+    initial_backoff: float
+    max_backoff: float
+    multiplier: float
+    jitter: bool
+
+    def __init__(
+        self,
+        max_attempts: int = 3,
+        initial_backoff: float = 0.5,
+        max_backoff: float = 30.0,
+        multiplier: float = 2.0,
+        jitter: bool = True,
+    ) -> None:
+        """
+        Retries transient failures (connection errors, timeouts, 429 and 5xx
+        responses) with exponential backoff. A Retry-After header sent with a
+        429 or 503 response takes precedence over the backoff, up to
+        max_backoff. A bucket creation retried after a failure that still
+        created the bucket succeeds. Points written without a timestamp get
+        the client's current time, so a retried write can't store them twice.
+
+        :param max_attempts: The total number of attempts, including the first one
+        :param initial_backoff: The delay in seconds before the first retry
+        :param max_backoff: The maximum delay in seconds between two attempts
+        :param multiplier: The factor applied to the delay after each retry
+        :param jitter: Whether to randomize each delay between half and all of it
+        """
+        self.max_attempts = max_attempts
+        self.initial_backoff = initial_backoff
+        self.max_backoff = max_backoff
+        self.multiplier = multiplier
+        self.jitter = jitter
+
+
 class Engine:
     host: str
     token: str
     org_id: str
+    precision: str
+    retry: Optional[RetryPolicy]
 
-    def __init__(self, host: str, token: str, org_id: str) -> None:
+    def __init__(
+        self,
+        host: str,
+        token: str,
+        org_id: str,
+        precision: str = "ns",
+        retry: Optional[RetryPolicy] = None,
+    ) -> None:
         self.host = host
         self.token = token
         self.org_id = org_id
+        self.precision = precision
+        self.retry = retry
 
 
-def create_engine(host: str, token: str, org_id: str) -> Engine:
+def create_engine(
+    host: str,
+    token: str,
+    org_id: str,
+    precision: str = "ns",
+    retry: Optional[RetryPolicy] = None,
+) -> Engine:
     """
     Creates a new Engine instance
 
     :param host: The host to connect to
     :param token: The token to use for authentication
     :param org_id: The organization id to use
+    :param precision: The precision of written timestamps, one of s, ms, us or ns
+    :param retry: The policy used to retry failed requests, no retries if None
 
     :return: the new Engine instance
     """
-    return Engine(host, token, org_id)
+    return Engine(host, token, org_id, precision, retry)
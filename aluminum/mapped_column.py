@@ -1,7 +1,8 @@
-from typing import Generic, TypeVar
+from typing import Generic, Iterable, Optional, TypeVar, Union
 
 from aluminum.abstract import AbstractMapped
 from aluminum.aluminum import _Mapped
+from aluminum.role import Role
 
 T = TypeVar("T")
 
@@ -9,9 +10,13 @@ T = TypeVar("T")
 class Mapped(AbstractMapped, Generic[T]):
     _col_name: str
     _mapped: _Mapped
+    _owner: Optional[type] = None
 
-    def __init__(self, col_name: str):
-        self._mapped = _Mapped(col_name)
+    def __init__(self, col_name: str, role: Role = Role.FIELD):
+        self._mapped = _Mapped(col_name, role.value)
+
+    def __set_name__(self, owner: type, name: str) -> None:
+        self._owner = owner
 
     def __lt__(self, value):
         return self._mapped.__lt__(value)
@@ -31,9 +36,53 @@ class Mapped(AbstractMapped, Generic[T]):
     def __ge__(self, value):
         return self._mapped.__ge__(value)
 
+    def in_(self, values: Iterable[T]):
+        return self._mapped.in_(values)
+
+    def not_in(self, values: Iterable[T]):
+        return self._mapped.not_in(values)
+
+    def is_(self, value: None):
+        return self._mapped.is_(value)
+
+    def is_not(self, value: None):
+        return self._mapped.is_not(value)
+
+    def matches(self, pattern: str):
+        return self._mapped.matches(pattern)
+
+    def not_matches(self, pattern: str):
+        return self._mapped.not_matches(pattern)
+
+    def startswith(self, prefix: str):
+        return self._mapped.startswith(prefix)
+
+    def endswith(self, suffix: str):
+        return self._mapped.endswith(suffix)
+
+    def contains(self, substring: str):
+        return self._mapped.contains(substring)
+
+    def asc(self):
+        return self._mapped.asc()
+
+    def desc(self):
+        return self._mapped.desc()
+
     def _get_col_name(self) -> str:
         return self._mapped._get_col_name()
 
+    def _get_role(self) -> Role:
+        return Role(self._mapped._get_role())
+
+
+def mapped_column(col_name: str, role: Union[Role, str] = Role.FIELD) -> Mapped[T]:
+    """
+    Declares a column of a bucket model
+
+    :param col_name: The name of the column in InfluxDB
+    :param role: Whether the column is the measurement, a tag, a field or the timestamp
 
-def mapped_column(col_name: str) -> Mapped[T]:
-    return Mapped(col_name)
+    :return: the mapped column
+    """
+    return Mapped(col_name, Role(role))
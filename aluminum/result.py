@@ -1,3 +1,8 @@
+from typing import Any, Generic, TypeVar
+
+T = TypeVar("T")
+
+
 class Result:
     _results: list[dict]
 
@@ -6,3 +11,33 @@ class Result:
 
     def all(self) -> list[dict]:  # noqa: D102,A003
         return self._results
+
+
+class Table(Generic[T]):
+    result: str
+    id: int
+    group_key: dict[str, Any]
+    records: list[T]
+
+    def __init__(
+        self, result: str, id: int, group_key: dict[str, Any], records: list[T]
+    ) -> None:
+        self.result = result
+        self.id = id
+        self.group_key = group_key
+        self.records = records
+
+    def __repr__(self) -> str:
+        return f"Table(result={self.result!r}, id={self.id}, group_key={self.group_key!r})"
+
+
+class QueryResult(list[T], Generic[T]):
+    tables: list[Table[T]]
+    results: dict[str, list[T]]
+
+    def __init__(
+        self, records: list[T], tables: list[Table[T]], results: dict[str, list[T]]
+    ) -> None:
+        super().__init__(records)
+        self.tables = tables
+        self.results = results
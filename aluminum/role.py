@@ -0,0 +1,8 @@
+from enum import Enum
+
+
+class Role(Enum):
+    MEASUREMENT = "measurement"
+    TAG = "tag"
+    FIELD = "field"
+    TIMESTAMP = "timestamp"
@@ -1,13 +1,41 @@
-from typing import Any, TypeVar
+from datetime import datetime, timedelta
+from functools import reduce
+from typing import Any, Optional, TypeVar, Union
 
 from aluminum.abstract import AbstractBase, AbstractSelect
-from aluminum.aluminum import _WhereClause, _Select
+from aluminum.aluminum import _Ordering, _WhereClause, _Select
+from aluminum.mapped_column import Mapped
 
 
 def select(*args):
     return Select(*args)
 
 
+def and_(*clauses: _WhereClause) -> _WhereClause:
+    """
+    Matches records that satisfy every clause, same as `clause & clause`.
+    """
+    if not clauses:
+        raise ValueError("and_() needs at least one clause")
+    return reduce(lambda left, right: left & right, clauses)
+
+
+def or_(*clauses: _WhereClause) -> _WhereClause:
+    """
+    Matches records that satisfy at least one clause, same as `clause | clause`.
+    """
+    if not clauses:
+        raise ValueError("or_() needs at least one clause")
+    return reduce(lambda left, right: left | right, clauses)
+
+
+def not_(clause: _WhereClause) -> _WhereClause:
+    """
+    Matches records that don't satisfy the clause, same as `~clause`.
+    """
+    return ~clause
+
+
 T = TypeVar("T")
 
 
@@ -17,36 +45,197 @@ TSelect = TypeVar("TSelect", bound="Select")
 class Select(AbstractSelect):
     _select_bucket: AbstractBase
     _where_clauses: tuple[_WhereClause, ...]
-    _raw_query: str
     _select: _Select
 
-    def __init__(self, select: AbstractBase) -> None:
-        self._select_bucket = select
-        self._select = _Select(select)
+    def __init__(self, *entities: Any) -> None:
+        """
+        Selects a model, or some columns of a model. Selecting columns keeps
+        only those in the query, and returns models with only those set.
+
+        :param entities: a model class, or columns of a single model
+        """
+        if len(entities) == 1 and isinstance(entities[0], type):
+            self._select_bucket = entities[0]
+            self._select = _Select(entities[0])
+            return
+        owners = {
+            column._owner if isinstance(column, Mapped) else None
+            for column in entities
+        }
+        if len(owners) != 1 or None in owners:
+            raise TypeError("select() expects a model or columns of a single model")
+        self._select_bucket = owners.pop()
+        self._select = _Select(
+            self._select_bucket, [column._mapped for column in entities]
+        )
 
     def where(self: TSelect, *args: _WhereClause) -> TSelect:
         for arg in args:
-            self._select._where(
-                left_operand=arg.get_left_operand(),
-                operator=arg.get_operator_str(),
-                right_operand=arg.get_right_operand(),
-            )
+            self._select._where(arg)
         self._where_clauses = args
         return self
 
-    def _create_bucket_str(self) -> None:
-        _bucket_name: str = self._select_bucket.schema()["title"]
-        self._select._create_bucket_str(_bucket_name)
+    def range(
+        self: TSelect,
+        start: Optional[Union[datetime, timedelta, str]] = None,
+        stop: Optional[Union[datetime, timedelta, str]] = None,
+    ) -> TSelect:
+        """
+        Restricts the query to a time range, the last hour by default.
+
+        :param start: a datetime, a timedelta or duration string relative to
+            now such as "-7d", or an ISO 8601 time
+        :param stop: same as start, now when omitted
+        """
+        self._select._range(start=start, stop=stop)
+        return self
 
-    def _create_filter_str(self) -> None:
-        self._select._create_filter_str()
+    def group_by(self: TSelect, *columns: Mapped[Any]) -> TSelect:
+        """
+        Groups the records by tags or the measurement, so that aggregations
+        run per group rather than per series. Executing the select returns a
+        mapping from the tuple of group values to the models of the group.
 
-    def _create_range_str(self) -> None:
-        self._select._create_range_str()
+        :param columns: the tag or measurement columns of the group key
+        """
+        self._select._group_by([column._mapped for column in columns])
+        return self
+
+    def aggregate_window(
+        self: TSelect,
+        every: Union[timedelta, str] = "1m",
+        fn: str = "mean",
+        create_empty: bool = False,
+    ) -> TSelect:
+        """
+        Aggregates each series into windows of the given duration. The results
+        are stamped with the stop time of their window. Where clauses on
+        fields can't be combined with an aggregation, unless they only read
+        the single selected field. mean, sum, min, max and median only read
+        the numeric fields, other fields are read as None.
+
+        :param every: the window duration, a timedelta or duration string such as "5m"
+        :param fn: one of mean, sum, count, min, max, median, first or last
+        :param create_empty: whether windows without data produce a result
+        """
+        self._select._aggregate_window(every, fn, create_empty)
+        return self
+
+    def derivative(
+        self: TSelect, unit: Union[timedelta, str] = "1s", non_negative: bool = False
+    ) -> TSelect:
+        """
+        Replaces the values of each series by their rate of change per unit.
+
+        :param unit: the time unit of the rate, a timedelta or duration string
+        :param non_negative: whether negative rates are dropped
+        """
+        self._select._derivative(unit, non_negative)
+        return self
+
+    def non_negative_derivative(
+        self: TSelect, unit: Union[timedelta, str] = "1s"
+    ) -> TSelect:
+        """
+        Replaces the values of each series by their rate of change per unit,
+        dropping negative rates such as counter resets.
+
+        :param unit: the time unit of the rate, a timedelta or duration string
+        """
+        self._select._derivative(unit, True)
+        return self
+
+    def difference(self: TSelect, non_negative: bool = False) -> TSelect:
+        """
+        Replaces the values of each series by the difference with the previous value.
+
+        :param non_negative: whether negative differences are dropped
+        """
+        self._select._difference(non_negative)
+        return self
 
-    def _create_raw_query(self):
-        self._create_bucket_str()
-        self._select._create_raw_query()
+    def moving_average(self: TSelect, n: int) -> TSelect:
+        """
+        Replaces the values of each series by the average of the last n values.
+        """
+        self._select._moving_average(n)
+        return self
+
+    def exponential_moving_average(self: TSelect, n: int) -> TSelect:
+        """
+        Replaces the values of each series by their exponential moving average
+        over n values.
+        """
+        self._select._exponential_moving_average(n)
+        return self
+
+    def cumulative_sum(self: TSelect) -> TSelect:
+        """
+        Replaces the values of each series by their running total.
+        """
+        self._select._cumulative_sum()
+        return self
+
+    def elapsed(
+        self: TSelect,
+        unit: Union[timedelta, str] = "1s",
+        column_name: str = "elapsed",
+    ) -> TSelect:
+        """
+        Adds the time since the previous record of each series, read into the
+        int field of the model with the same column name. That field is not
+        read from the stored fields.
+
+        :param unit: the time unit of the elapsed time
+        :param column_name: the column name of an int field of the model
+        """
+        self._select._elapsed(unit, column_name)
+        return self
+
+    def order_by(self: TSelect, *columns: Union[Mapped[Any], _Ordering]) -> TSelect:
+        """
+        Sorts the records of each series or group by the given columns, in
+        ascending order unless given as `column.desc()`.
+
+        :param columns: the columns to sort by, all in the same direction
+        """
+        self._select._order_by(
+            [c.asc() if isinstance(c, Mapped) else c for c in columns]
+        )
+        return self
+
+    def limit(self: TSelect, n: int) -> TSelect:
+        """
+        Keeps at most n records of each series or group.
+        """
+        self._select._limit(n)
+        return self
+
+    def offset(self: TSelect, n: int) -> TSelect:
+        """
+        Skips the first n records of each series or group, used with limit().
+        """
+        self._select._offset(n)
+        return self
+
+    def first(self: TSelect) -> TSelect:
+        """
+        Keeps only the first record, or the first of each group, in time order
+        unless sorted. Executing the select returns a single model or None.
+        """
+        self._select._first()
+        return self
+
+    def last(self: TSelect) -> TSelect:
+        """
+        Keeps only the last record, or the last of each group, in time order
+        unless sorted. Executing the select returns a single model or None.
+        """
+        self._select._last()
+        return self
 
-    def _get_raw_query(self) -> str:
-        return self._select._get_raw_query()
+    def to_flux(self) -> str:
+        """
+        Renders the select statement as a Flux query.
+        """
+        return self._select.to_flux()
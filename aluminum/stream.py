@@ -0,0 +1,31 @@
+from typing import Any, AsyncIterator, Type
+
+from aluminum.abstract import AbstractBase, AbstractQueryStream
+
+
+class QueryStream(AbstractQueryStream):
+
+    _stream: Any
+    _model: Type[AbstractBase]
+
+    def __init__(self, stream: Any, model: Type[AbstractBase]):
+        self._stream = stream
+        self._model = model
+
+    async def batches(self) -> AsyncIterator[list[AbstractBase]]:
+        async for batch in self._stream:
+            yield [self._model(**d) for d in batch]
+
+    async def __aiter__(self) -> AsyncIterator[AbstractBase]:
+        async for batch in self.batches():
+            for item in batch:
+                yield item
+
+    async def close(self) -> None:
+        await self._stream.close()
+
+    async def __aenter__(self) -> "QueryStream":
+        return self
+
+    async def __aexit__(self, *exc_info: Any) -> None:
+        await self.close()
@@ -0,0 +1,4 @@
+class UInt(int):
+    """
+    Annotation for columns stored as unsigned 64-bit integers in InfluxDB
+    """
@@ -0,0 +1,29 @@
+from typing import Any
+
+from aluminum.abstract import AbstractBase, AbstractBufferedWriter
+
+
+class BufferedWriter(AbstractBufferedWriter):
+
+    _writer: AbstractBufferedWriter
+
+    def __init__(self, writer: AbstractBufferedWriter):
+        self._writer = writer
+
+    def add(self, item: AbstractBase) -> None:
+        self._writer.add(item)
+
+    def pending(self) -> int:
+        return self._writer.pending()
+
+    async def flush(self) -> int:
+        return await self._writer.flush()
+
+    async def close(self) -> int:
+        return await self._writer.close()
+
+    async def __aenter__(self) -> "BufferedWriter":
+        return self
+
+    async def __aexit__(self, *exc_info: Any) -> None:
+        await self.close()
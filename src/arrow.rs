@@ -0,0 +1,257 @@
+use std::ffi::CString;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, Float64Builder, Int64Builder, NullBuilder, StringBuilder,
+    TimestampNanosecondBuilder, UInt64Builder,
+};
+use arrow_array::ffi_stream::FFI_ArrowArrayStream;
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchIterator, RecordBatchOptions};
+use arrow_schema::ffi::FFI_ArrowSchema;
+use arrow_schema::{DataType, Field, SchemaRef, TimeUnit};
+use influxdb2::api::write::TimestampPrecision;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+
+use crate::bucket::{record_value, Decoding};
+use crate::flux::{Decoded, FluxRecord};
+use crate::schema::{Column, ColumnRole, FieldType, Schema};
+
+/// Rows per record batch.
+const BATCH_ROWS: usize = 65_536;
+
+/// Query results decoded into Arrow record batches, exported through the
+/// Arrow PyCapsule interface (`__arrow_c_stream__`).
+#[pyclass]
+pub(crate) struct _ArrowResult {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+}
+
+impl _ArrowResult {
+    /// Decodes `records` into batches with one column per selected schema key.
+    pub(crate) fn decode(
+        schema: &Schema,
+        decoding: &Decoding,
+        precision: TimestampPrecision,
+        records: &[FluxRecord],
+    ) -> PyResult<Self> {
+        let columns = arrow_columns(schema, decoding)?;
+        let arrow_schema: SchemaRef = Arc::new(arrow_schema::Schema::new(
+            columns
+                .iter()
+                .map(|(key, _, field_type)| Field::new(*key, data_type(field_type), true))
+                .collect::<Vec<_>>(),
+        ));
+        let mut batches = Vec::new();
+        for chunk in records.chunks(BATCH_ROWS) {
+            let mut builders = columns
+                .iter()
+                .map(|(_, _, field_type)| ColumnBuilder::new(field_type, chunk.len()))
+                .collect::<Vec<_>>();
+            for record in chunk {
+                for ((key, column, field_type), builder) in columns.iter().zip(&mut builders) {
+                    let value = match record_value(record, column) {
+                        Some(value) => value.convert(key, field_type, precision)?,
+                        None => Decoded::Null,
+                    };
+                    builder.append(key, field_type, value)?;
+                }
+            }
+            let arrays = builders.into_iter().map(ColumnBuilder::finish).collect();
+            let options = RecordBatchOptions::new().with_row_count(Some(chunk.len()));
+            let batch = RecordBatch::try_new_with_options(arrow_schema.clone(), arrays, &options)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            batches.push(batch);
+        }
+        Ok(Self {
+            schema: arrow_schema,
+            batches,
+        })
+    }
+}
+
+#[pymethods]
+impl _ArrowResult {
+    #[getter]
+    pub(crate) fn num_rows(&self) -> usize {
+        self.batches.iter().map(RecordBatch::num_rows).sum()
+    }
+
+    #[getter]
+    pub(crate) fn column_names(&self) -> Vec<String> {
+        self.schema
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect()
+    }
+
+    pub(crate) fn __arrow_c_schema__<'p>(&self, py: Python<'p>) -> PyResult<&'p PyCapsule> {
+        let schema = FFI_ArrowSchema::try_from(self.schema.as_ref())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        PyCapsule::new(py, schema, Some(capsule_name("arrow_schema")))
+    }
+
+    /// Exports the batches as a new stream, the requested schema is ignored.
+    #[args(requested_schema = "None")]
+    pub(crate) fn __arrow_c_stream__<'p>(
+        &self,
+        py: Python<'p>,
+        requested_schema: Option<&PyAny>,
+    ) -> PyResult<&'p PyCapsule> {
+        let _ = requested_schema;
+        let reader = RecordBatchIterator::new(
+            self.batches.clone().into_iter().map(Ok),
+            self.schema.clone(),
+        );
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        PyCapsule::new(py, stream, Some(capsule_name("arrow_array_stream")))
+    }
+}
+
+fn capsule_name(name: &str) -> CString {
+    CString::new(name).expect("capsule names have no NUL byte")
+}
+
+/// The exported columns with their decoded types: the selected columns, or
+/// every column with the timestamp, measurement and tags first, followed by
+/// the group key columns that were not selected.
+fn arrow_columns<'s>(
+    schema: &'s Schema,
+    decoding: &Decoding,
+) -> PyResult<Vec<(&'s str, &'s Column, FieldType)>> {
+    let mut keys: Vec<&String> = match &decoding.columns {
+        Some(columns) => schema
+            .mapping
+            .keys()
+            .filter(|key| columns.contains(key))
+            .collect(),
+        None => schema.mapping.keys().collect(),
+    };
+    keys.sort_by_key(|key| {
+        let position = decoding
+            .columns
+            .as_ref()
+            .and_then(|columns| columns.iter().position(|column| column == *key));
+        (
+            position,
+            role_order(schema.mapping[*key].role),
+            key.as_str(),
+        )
+    });
+    for key in decoding.group_by.iter().flatten() {
+        if let Some((key, _)) = schema.mapping.get_key_value(key) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys.into_iter()
+        .map(|key| {
+            let column = &schema.mapping[key];
+            let field_type = decoding.field_type(column);
+            match field_type {
+                FieldType::Dict { .. } | FieldType::List { .. } | FieldType::Tuple { .. } => {
+                    Err(PyTypeError::new_err(format!(
+                        "Column '{}' of type {} can't be exported to Arrow",
+                        key,
+                        field_type.name()
+                    )))
+                }
+                _ => Ok((key.as_str(), column, field_type)),
+            }
+        })
+        .collect()
+}
+
+fn role_order(role: ColumnRole) -> u8 {
+    match role {
+        ColumnRole::Timestamp => 0,
+        ColumnRole::Measurement => 1,
+        ColumnRole::Tag => 2,
+        ColumnRole::Field => 3,
+    }
+}
+
+fn data_type(field_type: &FieldType) -> DataType {
+    match field_type {
+        FieldType::Int => DataType::Int64,
+        FieldType::UInt => DataType::UInt64,
+        FieldType::Float => DataType::Float64,
+        FieldType::Bool => DataType::Boolean,
+        FieldType::DateTime => DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        FieldType::None => DataType::Null,
+        _ => DataType::Utf8,
+    }
+}
+
+enum ColumnBuilder {
+    Str(StringBuilder),
+    Int(Int64Builder),
+    UInt(UInt64Builder),
+    Float(Float64Builder),
+    Bool(BooleanBuilder),
+    DateTime(TimestampNanosecondBuilder),
+    None(NullBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(field_type: &FieldType, capacity: usize) -> Self {
+        match field_type {
+            FieldType::Int => Self::Int(Int64Builder::with_capacity(capacity)),
+            FieldType::UInt => Self::UInt(UInt64Builder::with_capacity(capacity)),
+            FieldType::Float => Self::Float(Float64Builder::with_capacity(capacity)),
+            FieldType::Bool => Self::Bool(BooleanBuilder::with_capacity(capacity)),
+            FieldType::DateTime => Self::DateTime(
+                TimestampNanosecondBuilder::with_capacity(capacity).with_timezone("UTC"),
+            ),
+            FieldType::None => Self::None(NullBuilder::new()),
+            _ => Self::Str(StringBuilder::with_capacity(capacity, capacity * 8)),
+        }
+    }
+
+    fn append(&mut self, key: &str, field_type: &FieldType, value: Decoded) -> PyResult<()> {
+        let out_of_range = |value: &dyn std::fmt::Display| {
+            PyTypeError::new_err(format!(
+                "Column '{}' can't hold {} as {}",
+                key,
+                value,
+                field_type.name()
+            ))
+        };
+        match (self, value) {
+            (Self::Str(builder), Decoded::Null) => builder.append_null(),
+            (Self::Int(builder), Decoded::Null) => builder.append_null(),
+            (Self::UInt(builder), Decoded::Null) => builder.append_null(),
+            (Self::Float(builder), Decoded::Null) => builder.append_null(),
+            (Self::Bool(builder), Decoded::Null) => builder.append_null(),
+            (Self::DateTime(builder), Decoded::Null) => builder.append_null(),
+            (Self::None(builder), Decoded::Null) => builder.append_null(),
+            (Self::Str(builder), Decoded::Str(v)) => builder.append_value(v),
+            (Self::Int(builder), Decoded::Int(v)) => builder.append_value(v),
+            (Self::Int(_), Decoded::UInt(v)) => return Err(out_of_range(&v)),
+            (Self::UInt(builder), Decoded::UInt(v)) => builder.append_value(v),
+            (Self::Float(builder), Decoded::Float(v)) => builder.append_value(v),
+            (Self::Bool(builder), Decoded::Bool(v)) => builder.append_value(v),
+            (Self::DateTime(builder), Decoded::DateTime(v)) => {
+                builder.append_value(v.timestamp_nanos_opt().ok_or_else(|| out_of_range(&v))?)
+            }
+            (_, value) => return Err(out_of_range(&format!("{:?}", value))),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            Self::Str(mut builder) => Arc::new(builder.finish()),
+            Self::Int(mut builder) => Arc::new(builder.finish()),
+            Self::UInt(mut builder) => Arc::new(builder.finish()),
+            Self::Float(mut builder) => Arc::new(builder.finish()),
+            Self::Bool(mut builder) => Arc::new(builder.finish()),
+            Self::DateTime(mut builder) => Arc::new(builder.finish()),
+            Self::None(mut builder) => Arc::new(builder.finish()),
+        }
+    }
+}
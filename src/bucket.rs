@@ -1,58 +1,309 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use futures::prelude::*;
-use influxdb2::models::data_point::DataPoint;
-use influxdb2::models::Query;
-use influxdb2::Client;
-use influxdb2::FromDataPoint;
+use influxdb2::api::write::TimestampPrecision;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use pyo3::types::{PyDict, PyList, PyTuple};
+
+use crate::arrow::_ArrowResult;
+use crate::connection::{Connection, HttpError};
+use crate::flux::{merge_fields, parse_records, parse_tables, FluxError, FluxRecord, FluxValue};
+use crate::point::{chunk_points, extract_timestamp, FieldValue, Point};
+use crate::query::{Aggregate, Transform};
+use crate::schema::{Column, ColumnRole, FieldType, Schema};
+use crate::select::_Select;
+use crate::stream::_QueryStream;
+use crate::writer::{_BufferedWriter, WriterOptions};
+
+/// How the records of a query are decoded.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Decoding {
+    /// The aggregate applied to the fields, which decides their type.
+    pub(crate) aggregate: Option<Aggregate>,
+    /// The transformations applied to the fields after the aggregate.
+    pub(crate) transforms: Vec<Transform>,
+    /// Schema keys whose values group the records, in the order of the key.
+    pub(crate) group_by: Option<Vec<String>>,
+    /// Whether a single record is expected, returned as the model or `None`.
+    pub(crate) single: bool,
+    /// Schema keys of the selected columns, every column when `None`.
+    pub(crate) columns: Option<Vec<String>>,
+}
+
+impl Decoding {
+    /// Decodes `record` into a dict of the selected schema keys, along with
+    /// the tuple of its group key when grouped.
+    pub(crate) fn decode<'p>(
+        &self,
+        py: Python<'p>,
+        schema: &Schema,
+        precision: TimestampPrecision,
+        record: &FluxRecord,
+    ) -> PyResult<(&'p PyDict, Option<&'p PyTuple>)> {
+        let data = transform_record(py, schema, precision, self, record)?;
+        let key = self.group_by.as_ref().map(|keys| {
+            PyTuple::new(
+                py,
+                keys.iter()
+                    .map(|key| data.get_item(key))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let data = match &self.columns {
+            Some(columns) => {
+                let selected = PyDict::new(py);
+                for key in columns {
+                    selected.set_item(key, data.get_item(key))?;
+                }
+                selected
+            }
+            None => data,
+        };
+        Ok((data, key))
+    }
+
+    /// The type `column` is decoded as, fields taking the type of the
+    /// aggregate's result, then of each transformation. The column added by
+    /// `elapsed()` keeps its declared type.
+    pub(crate) fn field_type(&self, column: &Column) -> FieldType {
+        let elapsed = self
+            .transforms
+            .iter()
+            .any(|transform| transform.elapsed_column() == Some(&column.name));
+        if column.role != ColumnRole::Field || elapsed {
+            return column.field_type.clone();
+        }
+        let field_type = match self.aggregate {
+            Some(aggregate) => aggregate.result_type(&column.field_type),
+            None => column.field_type.clone(),
+        };
+        self.transforms
+            .iter()
+            .fold(field_type, |field_type, transform| {
+                transform.result_type(&field_type)
+            })
+    }
+}
+
+/// Raises query failures as `RuntimeError`, invalid responses as `ValueError`.
+pub(crate) fn flux_error(error: FluxError) -> PyErr {
+    match error {
+        FluxError::Query(_) => PyRuntimeError::new_err(error.to_string()),
+        FluxError::Invalid(_) => PyValueError::new_err(error.to_string()),
+    }
+}
 
-use crate::schema::Schema;
 #[pyclass(subclass)]
 pub(crate) struct _Bucket {
     pub(crate) name: String,
     pub(crate) meta: BucketMeta,
-    pub(crate) client: Client,
+    pub(crate) connection: Connection,
 }
 impl _Bucket {
-    pub(crate) fn new(name: String, meta: BucketMeta, client: Client) -> Self {
-        Self { name, meta, client }
+    pub(crate) fn new(name: String, meta: BucketMeta, connection: Connection) -> Self {
+        Self {
+            name,
+            meta,
+            connection,
+        }
+    }
+
+    /// Runs `query` and decodes its records with the bucket's schema.
+    fn query<'a>(&self, py: Python<'a>, query: String, decoding: Decoding) -> PyResult<&'a PyAny> {
+        let connection = self.connection.clone();
+        let name = self.name.clone();
+        let schema = self.meta.schema.clone();
+
+        pyo3_asyncio::tokio::future_into_py_with_locals(
+            py,
+            pyo3_asyncio::tokio::get_current_locals(py)?,
+            async move {
+                let text = connection
+                    .query(&query)
+                    .await
+                    .map_err(HttpError::query_error)?;
+                let tables = parse_tables(&text).map_err(flux_error)?;
+
+                Python::with_gil(|py| {
+                    let result = PyDict::new(py);
+                    result.set_item("name", name)?;
+                    let table_list = PyList::empty(py);
+                    for table in &tables {
+                        let entry = PyDict::new(py);
+                        entry.set_item("result", &table.result)?;
+                        entry.set_item("table", table.id)?;
+                        let group_key = PyDict::new(py);
+                        if let Some(record) = table.records.first() {
+                            for column in table.group_key() {
+                                group_key.set_item(&column.name, record.get(&column.name))?;
+                            }
+                        }
+                        entry.set_item("group_key", group_key)?;
+                        let data = PyList::empty(py);
+                        for record in merge_fields(table.records.clone()) {
+                            let (record, _) =
+                                decoding.decode(py, &schema, connection.precision, &record)?;
+                            data.append(record)?;
+                        }
+                        entry.set_item("data", data)?;
+                        table_list.append(entry)?;
+                    }
+                    result.set_item("tables", table_list)?;
+
+                    // the fields of each result are merged separately
+                    let mut named: Vec<(String, Vec<FluxRecord>)> = Vec::new();
+                    for table in tables {
+                        match named.iter_mut().find(|(name, _)| *name == table.result) {
+                            Some((_, records)) => records.extend(table.records),
+                            None => named.push((table.result, table.records)),
+                        }
+                    }
+                    let list = PyList::empty(py);
+                    let groups = PyDict::new(py);
+                    let results = PyDict::new(py);
+                    for (name, records) in named {
+                        let result_list = PyList::empty(py);
+                        for record in merge_fields(records) {
+                            let (data, key) =
+                                decoding.decode(py, &schema, connection.precision, &record)?;
+                            if let Some(key) = key {
+                                match groups.get_item(key) {
+                                    Some(group) => group.downcast::<PyList>()?.append(data)?,
+                                    None => groups.set_item(key, PyList::new(py, [data]))?,
+                                }
+                            }
+                            result_list.append(data)?;
+                            list.append(data)?;
+                        }
+                        results.set_item(name, result_list)?;
+                    }
+                    result.set_item("data", list)?;
+                    result.set_item("results", results)?;
+                    if decoding.group_by.is_some() {
+                        result.set_item("groups", groups)?;
+                    }
+                    result.set_item("single", decoding.single)?;
+
+                    Ok(Into::<PyObject>::into(result))
+                })
+            },
+        )
     }
 }
 
 #[pymethods]
 impl _Bucket {
     pub(crate) fn add<'b>(&self, py: Python<'b>, item: Py<PyAny>) -> PyResult<&'b PyAny> {
-        let client = self.client.clone();
+        let connection = self.connection.clone();
         let name = self.name.clone();
         let schema = self.meta.schema.clone();
 
-        let records = transform_point(&schema, &item)?;
-        let (measurement, records) = records;
-
-        let mut point = DataPoint::builder(measurement);
-        for (k, v) in records {
-            if k == "field" {
-                point = point.field(k.clone(), v.clone());
-            } else if k == "tag" {
-                point = point.tag(k.clone(), v.clone());
-            }
-        }
-        let points = vec![point.build().unwrap()];
+        let points = vec![transform_point(
+            &name,
+            &schema,
+            connection.precision,
+            &item,
+        )?];
 
         pyo3_asyncio::tokio::future_into_py_with_locals(
             py,
             pyo3_asyncio::tokio::get_current_locals(py)?,
             async move {
-                client
-                    .write(&name, stream::iter(points))
+                connection
+                    .write(&name, &points)
                     .await
-                    .map_err(|e| pyo3::exceptions::PyConnectionError::new_err(e.to_string()))?;
+                    .map_err(|e| e.write_error(0, points.len()))?;
                 Python::with_gil(|py| Ok(true.into_py(py)))
             },
         )
     }
+
+    #[args(max_points = "5000", max_bytes = "10_000_000")]
+    pub(crate) fn add_all<'b>(
+        &self,
+        py: Python<'b>,
+        items: Vec<Py<PyAny>>,
+        max_points: usize,
+        max_bytes: usize,
+    ) -> PyResult<&'b PyAny> {
+        if max_points == 0 || max_bytes == 0 {
+            return Err(PyValueError::new_err(
+                "max_points and max_bytes must be greater than 0",
+            ));
+        }
+        let connection = self.connection.clone();
+        let name = self.name.clone();
+        let schema = self.meta.schema.clone();
+
+        let points = items
+            .iter()
+            .map(|item| transform_point(&name, &schema, connection.precision, item))
+            .collect::<PyResult<Vec<Point>>>()?;
+        let total = points.len();
+        let batches = chunk_points(points, max_points, max_bytes);
+
+        pyo3_asyncio::tokio::future_into_py_with_locals(
+            py,
+            pyo3_asyncio::tokio::get_current_locals(py)?,
+            async move {
+                let mut written = 0;
+                let mut requests = 0;
+                for batch in batches {
+                    connection
+                        .write(&name, &batch)
+                        .await
+                        .map_err(|e| e.write_error(written, total))?;
+                    written += batch.len();
+                    requests += 1;
+                }
+                Python::with_gil(|py| {
+                    let summary = PyDict::new(py);
+                    summary.set_item("points", written)?;
+                    summary.set_item("requests", requests)?;
+                    Ok(Into::<PyObject>::into(summary))
+                })
+            },
+        )
+    }
+
+    #[args(
+        max_points = "5000",
+        max_bytes = "10_000_000",
+        flush_interval = "1.0",
+        on_error = "None"
+    )]
+    pub(crate) fn writer(
+        &self,
+        max_points: usize,
+        max_bytes: usize,
+        flush_interval: f64,
+        on_error: Option<Py<PyAny>>,
+    ) -> PyResult<_BufferedWriter> {
+        if max_points == 0 || max_bytes == 0 {
+            return Err(PyValueError::new_err(
+                "max_points and max_bytes must be greater than 0",
+            ));
+        }
+        let flush_interval = Duration::try_from_secs_f64(flush_interval)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| {
+                PyValueError::new_err("flush_interval must be a positive number of seconds")
+            })?;
+        Ok(_BufferedWriter::new(
+            self.name.clone(),
+            self.meta.schema.clone(),
+            self.connection.clone(),
+            WriterOptions {
+                max_points,
+                max_bytes,
+                flush_interval,
+                on_error,
+            },
+        ))
+    }
+
     pub(crate) fn to_dict(&self) -> PyResult<PyObject> {
         Python::with_gil(|py| {
             let dict = PyDict::new(py);
@@ -63,37 +314,59 @@ impl _Bucket {
     }
 
     pub(crate) fn raw_query<'a>(&self, query: String, py: Python<'a>) -> PyResult<&'a PyAny> {
-        let client = self.client.clone();
-        let name = self.name.clone();
+        self.query(py, query, Decoding::default())
+    }
+
+    pub(crate) fn execute<'a>(&self, select: &_Select, py: Python<'a>) -> PyResult<&'a PyAny> {
+        self.query(py, select.query.to_flux()?, select.decoding())
+    }
+
+    pub(crate) fn execute_arrow<'a>(
+        &self,
+        select: &_Select,
+        py: Python<'a>,
+    ) -> PyResult<&'a PyAny> {
+        let connection = self.connection.clone();
+        let schema = self.meta.schema.clone();
+        let query = select.query.to_flux()?;
+        let decoding = select.decoding();
 
         pyo3_asyncio::tokio::future_into_py_with_locals(
             py,
             pyo3_asyncio::tokio::get_current_locals(py)?,
             async move {
-                let query = Query::new(query);
-                let stream: Vec<QueryResult> = client
-                    .query::<QueryResult>(Some(query))
+                let text = connection
+                    .query(&query)
                     .await
-                    .map_err(|e| pyo3::exceptions::PyConnectionError::new_err(e.to_string()))?;
-
-                Python::with_gil(|py| {
-                    let result = PyDict::new(py);
-                    result.set_item("name", name)?;
-                    let list = PyList::empty(py);
-                    for item in stream {
-                        let dict = PyDict::new(py);
-                        dict.set_item("measurement", item.measurement)?;
-                        dict.set_item("tag", item.tag)?;
-                        dict.set_item("field", item.field.parse::<i32>().unwrap())?;
-                        list.append(dict)?;
-                    }
-                    result.set_item("data", list)?;
-
-                    Ok(Into::<PyObject>::into(result))
-                })
+                    .map_err(HttpError::query_error)?;
+                let records = merge_fields(parse_records(&text).map_err(flux_error)?);
+                let result =
+                    _ArrowResult::decode(&schema, &decoding, connection.precision, &records)?;
+                Python::with_gil(|py| Ok(Py::new(py, result)?.into_py(py)))
             },
         )
     }
+
+    pub(crate) fn stream(&self, select: &_Select) -> PyResult<_QueryStream> {
+        let decoding = select.decoding();
+        // the stream yields records as they arrive, not groups or a single model
+        if decoding.group_by.is_some() {
+            return Err(PyValueError::new_err(
+                "stream() can't return groups, execute() a grouped select instead",
+            ));
+        }
+        if decoding.single {
+            return Err(PyValueError::new_err(
+                "stream() can't return a single record, execute() first() or last() instead",
+            ));
+        }
+        Ok(_QueryStream::new(
+            self.connection.clone(),
+            select.query.to_flux()?,
+            self.meta.schema.clone(),
+            decoding,
+        ))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -114,43 +387,82 @@ impl BucketMeta {
     }
 }
 
-#[derive(FromDataPoint, Debug)]
-pub struct QueryResult {
-    pub measurement: String,
-    pub tag: String,
-    pub field: String,
-}
+pub(crate) fn transform_point(
+    bucket_name: &str,
+    schema: &Schema,
+    precision: TimestampPrecision,
+    obj: &Py<PyAny>,
+) -> PyResult<Point> {
+    Python::with_gil(|py| {
+        let obj = match obj.extract::<HashMap<String, Py<PyAny>>>(py) {
+            Ok(v) => v,
+            Err(_) => obj.getattr(py, "dict")?.call0(py)?.extract(py)?,
+        };
+        let measurement = match schema.measurement() {
+            Some((key, _)) => obj
+                .get(key)
+                .filter(|value| !value.is_none(py))
+                .map(|value| value.to_string())
+                .ok_or_else(|| PyValueError::new_err(format!("Missing measurement '{}'", key)))?,
+            None => bucket_name.to_string(),
+        };
 
-impl Default for QueryResult {
-    fn default() -> Self {
-        Self {
-            measurement: String::new(),
-            tag: String::new(),
-            field: String::new(),
+        let mut point = Point::new(measurement);
+        for (key, column) in schema.mapping.iter() {
+            // `None` columns, such as those missing from a queried record,
+            // are left out of the point
+            if let Some(value) = obj.get(key).filter(|value| !value.is_none(py)) {
+                match column.role {
+                    ColumnRole::Tag => point.tag(column.name.clone(), value.to_string()),
+                    ColumnRole::Field => {
+                        let value = FieldValue::extract(&column.field_type, key, value.as_ref(py))?;
+                        point.field(column.name.clone(), value);
+                    }
+                    ColumnRole::Timestamp => {
+                        point.timestamp(extract_timestamp(key, value.as_ref(py), precision)?)
+                    }
+                    ColumnRole::Measurement => {}
+                }
+            };
         }
-    }
+        point.validate()
+    })
 }
 
-pub(crate) fn transform_point(
+/// Builds the keyword arguments of a model instance from a query record.
+/// Every mapped column is read by its column name, the measurement and the
+/// timestamp falling back to `_measurement` and `_time`. Missing columns are `None`,
+/// measurements and tags that don't convert to their type are kept as strings.
+pub(crate) fn transform_record<'p>(
+    py: Python<'p>,
     schema: &Schema,
-    obj: &Py<PyAny>,
-) -> PyResult<(String, Vec<(String, String)>)> {
-    let obj = Python::with_gil(|py| match obj.extract::<HashMap<String, Py<PyAny>>>(py) {
-        Ok(v) => Ok(v),
-        Err(_) => obj.getattr(py, "dict")?.call0(py)?.extract(py),
-    })?;
-
-    let mut records: Vec<(String, String)> = Vec::with_capacity(obj.len());
-    let mut measurement = String::new();
-
-    for field in schema.mapping.keys() {
-        if let Some(value) = obj.get(field) {
-            if field == "measurement" {
-                measurement = value.to_string();
-            }
-            records.push((field.clone(), value.to_string()));
+    precision: TimestampPrecision,
+    decoding: &Decoding,
+    record: &FluxRecord,
+) -> PyResult<&'p PyDict> {
+    let dict = PyDict::new(py);
+    for (key, column) in schema.mapping.iter() {
+        let value = match record_value(record, column) {
+            Some(value) => match value.decode(py, key, &decoding.field_type(column), precision) {
+                // measurements and tags are written as the `str()` of any value
+                Err(_) if matches!(column.role, ColumnRole::Measurement | ColumnRole::Tag) => {
+                    value.to_string().into_py(py)
+                }
+                result => result?,
+            },
+            None => py.None(),
         };
+        dict.set_item(key, value)?;
     }
+    Ok(dict)
+}
 
-    Ok((measurement, records))
+/// Reads `column` from `record`, the measurement and the timestamp falling
+/// back to `_measurement` and `_time`.
+pub(crate) fn record_value<'r>(record: &'r FluxRecord, column: &Column) -> Option<&'r FluxValue> {
+    record.get(&column.name).or_else(|| match column.role {
+        ColumnRole::Measurement => record.get("_measurement"),
+        ColumnRole::Timestamp => record.get("_time"),
+        ColumnRole::Tag | ColumnRole::Field => None,
+    })
 }
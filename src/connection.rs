@@ -0,0 +1,328 @@
+use std::fmt;
+use std::time::Duration;
+
+use bytes::BufMut;
+use chrono::{DateTime, Utc};
+use influxdb2::api::write::TimestampPrecision;
+use influxdb2::models::{Buckets, HealthCheck, PostBucketRequest, Query, Status, WriteDataPoint};
+use influxdb2::Client;
+use pyo3::exceptions::{PyConnectionError, PyRuntimeError};
+use pyo3::prelude::*;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::engine::{parse_precision, PyEngine};
+use crate::point::{to_epoch, Point};
+use crate::retry::{is_transient_reqwest, is_transient_status, with_retry, RetryPolicy, Transient};
+
+/// Everything a store and its buckets need to talk to the server.
+#[derive(Clone, Debug)]
+pub(crate) struct Connection {
+    pub(crate) client: Client,
+    pub(crate) precision: TimestampPrecision,
+    pub(crate) retry: RetryPolicy,
+    http: reqwest::Client,
+    token: String,
+}
+
+impl Connection {
+    pub(crate) fn new(engine: &PyEngine) -> PyResult<Self> {
+        let retry = match &engine.retry {
+            Some(retry) => retry.clone().validate()?,
+            None => RetryPolicy::none(),
+        };
+        Ok(Self {
+            client: Client::new(&engine.host, &engine.org_id, &engine.token),
+            precision: parse_precision(&engine.precision)?,
+            retry,
+            http: reqwest::Client::new(),
+            token: engine.token.clone(),
+        })
+    }
+
+    /// Writes `points` as line protocol, retrying transient failures.
+    /// A failed attempt may still have stored the points, so when writes can
+    /// be retried, points without a timestamp get the current time before the
+    /// first attempt and a retry overwrites them instead of adding new ones.
+    pub(crate) async fn write(&self, bucket: &str, points: &[Point]) -> Result<(), HttpError> {
+        let now = match self.retry.max_attempts {
+            1 => None,
+            _ => to_epoch(&Utc::now(), self.precision),
+        };
+        let mut body = bytes::BytesMut::new().writer();
+        for point in points {
+            let result = match now {
+                Some(now) if !point.has_timestamp() => {
+                    let mut point = point.clone();
+                    point.timestamp(now);
+                    point.write_data_point_to(&mut body)
+                }
+                _ => point.write_data_point_to(&mut body),
+            };
+            result.map_err(|e| HttpError::new(None, e.to_string()))?;
+        }
+        let body = body.into_inner().freeze();
+        with_retry(&self.retry, || self.write_once(bucket, body.clone())).await
+    }
+
+    async fn write_once(&self, bucket: &str, body: bytes::Bytes) -> Result<(), HttpError> {
+        let precision = match self.precision {
+            TimestampPrecision::Seconds => "s",
+            TimestampPrecision::Milliseconds => "ms",
+            TimestampPrecision::Microseconds => "us",
+            TimestampPrecision::Nanoseconds => "ns",
+        };
+        let request = self
+            .request(Method::POST, "/api/v2/write")
+            .query(&[
+                ("bucket", bucket),
+                ("org", &self.client.org),
+                ("precision", precision),
+            ])
+            .body(body);
+        self.send(request, &[StatusCode::NO_CONTENT]).await?;
+        Ok(())
+    }
+
+    /// Runs a Flux query and returns the annotated CSV response, retrying
+    /// transient failures.
+    pub(crate) async fn query(&self, flux: &str) -> Result<String, HttpError> {
+        let body = query_body(flux)?;
+        with_retry(&self.retry, || async {
+            let response = self.query_once(body.clone()).await?;
+            response.text().await.map_err(HttpError::from)
+        })
+        .await
+    }
+
+    /// Runs a Flux query and returns the response once its status is known,
+    /// for the body to be read as it arrives. Only sending the query is
+    /// retried.
+    pub(crate) async fn query_stream(&self, flux: &str) -> Result<reqwest::Response, HttpError> {
+        let body = query_body(flux)?;
+        with_retry(&self.retry, || self.query_once(body.clone())).await
+    }
+
+    async fn query_once(&self, body: Vec<u8>) -> Result<reqwest::Response, HttpError> {
+        let request = self
+            .request(Method::POST, "/api/v2/query")
+            .query(&[("org", &self.client.org)])
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/csv")
+            .body(body);
+        self.send(request, &[StatusCode::OK]).await
+    }
+
+    /// Whether the server reports itself healthy, retrying transient
+    /// failures. An unhealthy server answers `503` with its status.
+    pub(crate) async fn healthy(&self) -> Result<bool, HttpError> {
+        let health: HealthCheck = with_retry(&self.retry, || async {
+            let request = self.request(Method::GET, "/health");
+            let response = self
+                .send(request, &[StatusCode::OK, StatusCode::SERVICE_UNAVAILABLE])
+                .await?;
+            json(response).await
+        })
+        .await?;
+        Ok(health.status == Status::Pass)
+    }
+
+    /// Creates the bucket `name`, retrying transient failures. A failed
+    /// attempt may still have created the bucket, so a conflict on a retry
+    /// counts as created.
+    pub(crate) async fn create_bucket(&self, name: &str) -> Result<(), HttpError> {
+        let body = serde_json::to_vec(&PostBucketRequest::new(
+            self.client.org.clone(),
+            name.to_string(),
+        ))
+        .map_err(|e| HttpError::new(None, e.to_string()))?;
+        let mut retried = false;
+        with_retry(&self.retry, || {
+            let request = self
+                .request(Method::POST, "/api/v2/buckets")
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+            let is_retry = std::mem::replace(&mut retried, true);
+            async move {
+                match self.send(request, &[StatusCode::CREATED]).await {
+                    Err(e) if is_retry && e.is_conflict() => Ok(()),
+                    result => result.map(|_| ()),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Deletes the bucket `name` if it exists, retrying transient failures.
+    /// A bucket missing on a retry was deleted by a failed attempt.
+    pub(crate) async fn delete_bucket(&self, name: &str) -> Result<(), HttpError> {
+        let buckets: Buckets = with_retry(&self.retry, || async {
+            let request = self
+                .request(Method::GET, "/api/v2/buckets")
+                .query(&[("orgID", self.client.org.as_str()), ("name", name)]);
+            json(self.send(request, &[StatusCode::OK]).await?).await
+        })
+        .await?;
+        let bucket = buckets.buckets.into_iter().find(|b| b.name == name);
+        let bucket_id = match bucket.and_then(|bucket| bucket.id) {
+            Some(bucket_id) => bucket_id,
+            None => return Ok(()),
+        };
+        let path = format!("/api/v2/buckets/{}", bucket_id);
+        let mut retried = false;
+        with_retry(&self.retry, || {
+            let request = self.request(Method::DELETE, &path);
+            let is_retry = std::mem::replace(&mut retried, true);
+            async move {
+                match self.send(request, &[StatusCode::NO_CONTENT]).await {
+                    Err(e) if is_retry && e.status == Some(StatusCode::NOT_FOUND) => Ok(()),
+                    result => result.map(|_| ()),
+                }
+            }
+        })
+        .await
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let mut url = self.client.base.clone();
+        url.set_path(path);
+        let request = self.http.request(method, url);
+        if self.token.is_empty() {
+            request
+        } else {
+            request.header("Authorization", format!("Token {}", self.token))
+        }
+    }
+
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        expected: &[StatusCode],
+    ) -> Result<reqwest::Response, HttpError> {
+        let response = request.send().await.map_err(HttpError::from)?;
+        let status = response.status();
+        if expected.contains(&status) {
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let text = response.text().await.unwrap_or_default();
+        Err(HttpError {
+            retry_after,
+            ..HttpError::new(
+                Some(status),
+                format!("HTTP request returned an error: {}, `{}`", status, text),
+            )
+        })
+    }
+}
+
+async fn json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, HttpError> {
+    let body = response.bytes().await.map_err(HttpError::from)?;
+    serde_json::from_slice(&body).map_err(|e| {
+        HttpError::new(
+            None,
+            format!("Error while decoding the HTTP response: {}", e),
+        )
+    })
+}
+
+fn query_body(flux: &str) -> Result<Vec<u8>, HttpError> {
+    serde_json::to_vec(&Query::new(flux.to_string()))
+        .map_err(|e| HttpError::new(None, e.to_string()))
+}
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[derive(Debug)]
+pub(crate) struct HttpError {
+    status: Option<StatusCode>,
+    message: String,
+    transient: bool,
+    retry_after: Option<Duration>,
+}
+
+impl HttpError {
+    fn new(status: Option<StatusCode>, message: String) -> Self {
+        Self {
+            status,
+            message,
+            transient: status.is_some_and(is_transient_status),
+            retry_after: None,
+        }
+    }
+
+    /// Raises a query refused by the server, with a `4xx` status other than
+    /// `429`, as `RuntimeError`, and other failures as `ConnectionError`.
+    pub(crate) fn query_error(self) -> PyErr {
+        let message = self.message.clone();
+        self.py_err(message)
+    }
+
+    /// Raises a failed write like `query_error()`, with how many of the
+    /// `total` points were written before it.
+    pub(crate) fn write_error(self, written: usize, total: usize) -> PyErr {
+        let message = format!("{} ({} of {} points written)", self.message, written, total);
+        self.py_err(message)
+    }
+
+    fn py_err(&self, message: String) -> PyErr {
+        match self.status {
+            Some(status) if status.is_client_error() && !self.transient => {
+                PyRuntimeError::new_err(message)
+            }
+            _ => PyConnectionError::new_err(message),
+        }
+    }
+
+    /// Whether the server refused to create something that already exists.
+    fn is_conflict(&self) -> bool {
+        matches!(
+            self.status,
+            Some(StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY)
+        )
+    }
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(error: reqwest::Error) -> Self {
+        Self {
+            transient: is_transient_reqwest(&error),
+            ..Self::new(
+                None,
+                format!("Error while processing the HTTP request: {}", error),
+            )
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Transient for HttpError {
+    fn is_transient(&self) -> bool {
+        self.transient
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self.status {
+            Some(StatusCode::TOO_MANY_REQUESTS) | Some(StatusCode::SERVICE_UNAVAILABLE) => {
+                self.retry_after
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDelta, PyString};
+
+const UNITS: &[(&str, Option<i128>)] = &[
+    ("ns", Some(1)),
+    ("us", Some(1_000)),
+    ("µs", Some(1_000)),
+    ("ms", Some(1_000_000)),
+    ("mo", None),
+    ("s", Some(1_000_000_000)),
+    ("m", Some(60_000_000_000)),
+    ("h", Some(3_600_000_000_000)),
+    ("d", Some(86_400_000_000_000)),
+    ("w", Some(604_800_000_000_000)),
+    ("y", None),
+];
+
+/// A signed Flux duration literal such as `-1h30m` or `1mo`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FluxDuration {
+    literal: String,
+    /// Length in nanoseconds, unknown for calendar units (`mo`, `y`).
+    nanos: Option<i128>,
+}
+
+impl FluxDuration {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let (negative, mut rest) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        if rest.is_empty() {
+            return None;
+        }
+        let mut nanos = Some(0i128);
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if digits == 0 {
+                return None;
+            }
+            let magnitude: i128 = rest[..digits].parse().ok()?;
+            rest = &rest[digits..];
+            let (unit, scale) = UNITS.iter().find(|(unit, _)| rest.starts_with(unit))?;
+            rest = &rest[unit.len()..];
+            nanos = match (nanos, scale) {
+                (Some(nanos), Some(scale)) => magnitude
+                    .checked_mul(*scale)
+                    .and_then(|part| part.checked_add(nanos)),
+                _ => None,
+            };
+        }
+        Some(Self {
+            literal: value.to_string(),
+            nanos: nanos.map(|nanos| if negative { -nanos } else { nanos }),
+        })
+    }
+
+    pub(crate) fn from_nanos(nanos: i128) -> Self {
+        let mut literal = String::new();
+        if nanos < 0 {
+            literal.push('-');
+        }
+        let mut rest = nanos.unsigned_abs();
+        for (unit, scale) in [
+            ("h", 3_600_000_000_000u128),
+            ("m", 60_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+            ("ns", 1),
+        ] {
+            if rest >= scale {
+                literal.push_str(&format!("{}{}", rest / scale, unit));
+                rest %= scale;
+            }
+        }
+        if nanos == 0 {
+            literal.push_str("0s");
+        }
+        Self {
+            literal,
+            nanos: Some(nanos),
+        }
+    }
+
+    /// Accepts a `timedelta` or a duration string.
+    pub(crate) fn extract(name: &str, value: &PyAny) -> PyResult<Self> {
+        if value.is_instance_of::<PyDelta>()? {
+            let delta: chrono::Duration = value.extract()?;
+            let nanos = delta
+                .num_nanoseconds()
+                .map(i128::from)
+                .unwrap_or_else(|| i128::from(delta.num_microseconds().unwrap_or(0)) * 1_000);
+            Ok(Self::from_nanos(nanos))
+        } else if value.is_instance_of::<PyString>()? {
+            let literal: &str = value.extract()?;
+            Self::parse(literal).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "'{}' expects a duration such as \"-1h30m\", got \"{}\"",
+                    name, literal
+                ))
+            })
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "'{}' expects a timedelta or a duration string, got {}",
+                name,
+                value.get_type().name()?
+            )))
+        }
+    }
+
+    pub(crate) fn nanos(&self) -> Option<i128> {
+        self.nanos
+    }
+}
+
+impl fmt::Display for FluxDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.literal)
+    }
+}
@@ -1,18 +1,47 @@
+use influxdb2::api::write::TimestampPrecision;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
+use crate::retry::RetryPolicy;
+
 #[derive(FromPyObject)]
 #[pyclass]
 pub struct PyEngine {
     pub host: String,
     pub token: String,
     pub org_id: String,
+    pub precision: String,
+    pub(crate) retry: Option<RetryPolicy>,
 }
 
-#[pyfunction]
-pub fn create_engine(host: String, token: String, org_id: String) -> PyResult<PyEngine> {
+#[pyfunction(precision = "\"ns\"", retry = "None")]
+pub fn create_engine(
+    host: String,
+    token: String,
+    org_id: String,
+    precision: &str,
+    retry: Option<RetryPolicy>,
+) -> PyResult<PyEngine> {
+    parse_precision(precision)?;
+    let retry = retry.map(RetryPolicy::validate).transpose()?;
     Ok(PyEngine {
         host,
         token,
         org_id,
+        precision: precision.to_string(),
+        retry,
     })
 }
+
+pub(crate) fn parse_precision(value: &str) -> PyResult<TimestampPrecision> {
+    match value {
+        "s" => Ok(TimestampPrecision::Seconds),
+        "ms" => Ok(TimestampPrecision::Milliseconds),
+        "us" => Ok(TimestampPrecision::Microseconds),
+        "ns" => Ok(TimestampPrecision::Nanoseconds),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid precision: {}. Expected one of s, ms, us, ns",
+            value
+        ))),
+    }
+}
@@ -0,0 +1,840 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use influxdb2::api::write::TimestampPrecision;
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+
+use crate::point::{parse_timestamp, to_epoch};
+use crate::schema::FieldType;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FluxValue {
+    Null,
+    String(String),
+    Double(f64),
+    Bool(bool),
+    Long(i64),
+    UnsignedLong(u64),
+    Duration(String),
+    Base64Binary(String),
+    Time(DateTime<FixedOffset>),
+}
+
+impl fmt::Display for FluxValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FluxValue::Null => write!(f, "null"),
+            FluxValue::String(v) | FluxValue::Duration(v) | FluxValue::Base64Binary(v) => {
+                write!(f, "{}", v)
+            }
+            FluxValue::Double(v) => write!(f, "{}", v),
+            FluxValue::Bool(v) => write!(f, "{}", v),
+            FluxValue::Long(v) => write!(f, "{}", v),
+            FluxValue::UnsignedLong(v) => write!(f, "{}", v),
+            FluxValue::Time(v) => write!(f, "{}", v.to_rfc3339()),
+        }
+    }
+}
+
+impl FluxValue {
+    fn type_name(&self) -> &str {
+        match self {
+            FluxValue::Null => "null",
+            FluxValue::String(_) => "string",
+            FluxValue::Double(_) => "double",
+            FluxValue::Bool(_) => "boolean",
+            FluxValue::Long(_) => "long",
+            FluxValue::UnsignedLong(_) => "unsignedLong",
+            FluxValue::Duration(_) => "duration",
+            FluxValue::Base64Binary(_) => "base64Binary",
+            FluxValue::Time(_) => "dateTime",
+        }
+    }
+
+    /// Converts the value into the Python type declared for column `name`.
+    /// Null values become `None`, epoch integers are expressed in `precision`.
+    pub(crate) fn decode(
+        &self,
+        py: Python,
+        name: &str,
+        field_type: &FieldType,
+        precision: TimestampPrecision,
+    ) -> PyResult<PyObject> {
+        Ok(match self.convert(name, field_type, precision)? {
+            Decoded::Null => py.None(),
+            Decoded::Str(v) => v.into_py(py),
+            Decoded::Int(v) => v.into_py(py),
+            Decoded::UInt(v) => v.into_py(py),
+            Decoded::Float(v) => v.into_py(py),
+            Decoded::Bool(v) => v.into_py(py),
+            Decoded::DateTime(v) => v.into_py(py),
+        })
+    }
+
+    /// Converts the value into the type declared for column `name`.
+    pub(crate) fn convert(
+        &self,
+        name: &str,
+        field_type: &FieldType,
+        precision: TimestampPrecision,
+    ) -> PyResult<Decoded> {
+        let value = match (field_type, self) {
+            (_, FluxValue::Null) => Decoded::Null,
+            (FieldType::Str, value) => Decoded::Str(value.to_string()),
+            (FieldType::Int, FluxValue::Long(v)) => Decoded::Int(*v),
+            (FieldType::Int, FluxValue::UnsignedLong(v)) => match i64::try_from(*v) {
+                Ok(v) => Decoded::Int(v),
+                Err(_) => Decoded::UInt(*v),
+            },
+            (FieldType::Int, FluxValue::Double(v)) if v.fract() == 0.0 => Decoded::Int(*v as i64),
+            (FieldType::Int, FluxValue::String(v)) => {
+                Decoded::Int(v.parse().map_err(|_| self.mismatch(name, field_type))?)
+            }
+            (FieldType::Int, FluxValue::Time(v)) => Decoded::Int(
+                to_epoch(&v.with_timezone(&Utc), precision)
+                    .ok_or_else(|| self.mismatch(name, field_type))?,
+            ),
+            (FieldType::UInt, FluxValue::Long(v)) if *v >= 0 => Decoded::UInt(*v as u64),
+            (FieldType::UInt, FluxValue::UnsignedLong(v)) => Decoded::UInt(*v),
+            (FieldType::UInt, FluxValue::String(v)) => {
+                Decoded::UInt(v.parse().map_err(|_| self.mismatch(name, field_type))?)
+            }
+            (FieldType::Float, FluxValue::Double(v)) => Decoded::Float(*v),
+            (FieldType::Float, FluxValue::Long(v)) => Decoded::Float(*v as f64),
+            (FieldType::Float, FluxValue::UnsignedLong(v)) => Decoded::Float(*v as f64),
+            (FieldType::Float, FluxValue::String(v)) => {
+                Decoded::Float(v.parse().map_err(|_| self.mismatch(name, field_type))?)
+            }
+            (FieldType::Bool, FluxValue::Bool(v)) => Decoded::Bool(*v),
+            (FieldType::Bool, FluxValue::String(v)) => {
+                Decoded::Bool(v.parse().map_err(|_| self.mismatch(name, field_type))?)
+            }
+            (FieldType::DateTime, FluxValue::Time(v)) => Decoded::DateTime(v.with_timezone(&Utc)),
+            (FieldType::DateTime, FluxValue::String(v)) => Decoded::DateTime(
+                parse_timestamp(v).ok_or_else(|| self.mismatch(name, field_type))?,
+            ),
+            _ => return Err(self.mismatch(name, field_type)),
+        };
+        Ok(value)
+    }
+
+    fn mismatch(&self, name: &str, field_type: &FieldType) -> PyErr {
+        PyTypeError::new_err(format!(
+            "Column '{}' expects a value of type {}, got {} {}",
+            name,
+            field_type.name(),
+            self.type_name(),
+            self
+        ))
+    }
+}
+
+/// A query value converted to the type declared for its column.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Decoded {
+    Null,
+    Str(String),
+    Int(i64),
+    /// Also holds `int` values beyond the range of `i64`.
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    DateTime(DateTime<Utc>),
+}
+
+/// Converts the value into the Python type of its Flux type, durations and
+/// binary values as `str`.
+impl ToPyObject for FluxValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        match self {
+            FluxValue::Null => py.None(),
+            FluxValue::String(v) | FluxValue::Duration(v) | FluxValue::Base64Binary(v) => {
+                v.to_object(py)
+            }
+            FluxValue::Double(v) => v.to_object(py),
+            FluxValue::Bool(v) => v.to_object(py),
+            FluxValue::Long(v) => v.to_object(py),
+            FluxValue::UnsignedLong(v) => v.to_object(py),
+            FluxValue::Time(v) => v.with_timezone(&Utc).to_object(py),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DataType {
+    String,
+    Double,
+    Bool,
+    Long,
+    UnsignedLong,
+    Duration,
+    Base64Binary,
+    Time,
+}
+
+impl DataType {
+    fn of(value: &str) -> Result<Self, FluxError> {
+        match value {
+            "string" => Ok(DataType::String),
+            "double" => Ok(DataType::Double),
+            "boolean" => Ok(DataType::Bool),
+            "long" => Ok(DataType::Long),
+            "unsignedLong" => Ok(DataType::UnsignedLong),
+            "duration" => Ok(DataType::Duration),
+            "base64Binary" => Ok(DataType::Base64Binary),
+            "dateTime:RFC3339" | "dateTime:RFC3339Nano" => Ok(DataType::Time),
+            _ => Err(FluxError::invalid(format!("unknown datatype: {}", value))),
+        }
+    }
+
+    fn parse(&self, value: &str) -> Result<FluxValue, FluxError> {
+        if value.is_empty() {
+            return Ok(FluxValue::Null);
+        }
+        let invalid = || FluxError::invalid(format!("invalid {:?} value: {}", self, value));
+        Ok(match self {
+            DataType::String => FluxValue::String(value.to_string()),
+            DataType::Double => FluxValue::Double(value.parse().map_err(|_| invalid())?),
+            DataType::Bool => FluxValue::Bool(value.parse().map_err(|_| invalid())?),
+            DataType::Long => FluxValue::Long(value.parse().map_err(|_| invalid())?),
+            DataType::UnsignedLong => {
+                FluxValue::UnsignedLong(value.parse().map_err(|_| invalid())?)
+            }
+            DataType::Duration => FluxValue::Duration(value.to_string()),
+            DataType::Base64Binary => FluxValue::Base64Binary(value.to_string()),
+            DataType::Time => {
+                FluxValue::Time(DateTime::parse_from_rfc3339(value).map_err(|_| invalid())?)
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum FluxError {
+    /// The response is not valid annotated CSV.
+    Invalid(String),
+    /// The query failed on the server and returned an error table.
+    Query(String),
+}
+
+impl FluxError {
+    fn invalid(message: String) -> Self {
+        FluxError::Invalid(message)
+    }
+}
+
+impl fmt::Display for FluxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FluxError::Invalid(message) => write!(f, "Invalid query response: {}", message),
+            FluxError::Query(message) => write!(f, "Query failed: {}", message),
+        }
+    }
+}
+
+pub(crate) type FluxRecord = BTreeMap<String, FluxValue>;
+
+/// The name of a result not named by `yield`.
+const DEFAULT_RESULT: &str = "_result";
+
+/// A column of a table, typed by its `#datatype` annotation.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FluxColumn {
+    pub(crate) name: String,
+    pub(crate) data_type: DataType,
+    /// Whether the column is part of the group key, from `#group`.
+    pub(crate) group: bool,
+    default: String,
+}
+
+/// The rows of a table, which share the values of its group key columns.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FluxTable {
+    /// The name given to the result by `yield`, `_result` by default.
+    pub(crate) result: String,
+    /// The number of the table within its result.
+    pub(crate) id: i64,
+    pub(crate) columns: Vec<FluxColumn>,
+    pub(crate) records: Vec<FluxRecord>,
+}
+
+impl FluxTable {
+    /// The columns of the group key, in the order of the table.
+    pub(crate) fn group_key(&self) -> impl Iterator<Item = &FluxColumn> {
+        self.columns.iter().filter(|column| column.group)
+    }
+}
+
+/// Parses an annotated CSV response into its tables, in the order of the
+/// response.
+pub(crate) fn parse_tables(text: &str) -> Result<Vec<FluxTable>, FluxError> {
+    RowParser::default().parse(text.as_bytes())
+}
+
+/// Parses an annotated CSV response into one record per row.
+pub(crate) fn parse_records(text: &str) -> Result<Vec<FluxRecord>, FluxError> {
+    Ok(flatten(parse_tables(text)?))
+}
+
+fn flatten(tables: Vec<FluxTable>) -> Vec<FluxRecord> {
+    tables.into_iter().flat_map(|table| table.records).collect()
+}
+
+/// Turns annotated CSV rows into tables of records, keeping track of the
+/// columns of the current table.
+#[derive(Default)]
+struct RowParser {
+    columns: Vec<FluxColumn>,
+    in_annotations: bool,
+    has_header: bool,
+    /// Whether a header was read since the last record, which starts a table.
+    new_header: bool,
+    is_error: bool,
+}
+
+impl RowParser {
+    /// Parses complete rows of annotated CSV. A table continued by a later
+    /// call is returned in parts.
+    fn parse(&mut self, text: &[u8]) -> Result<Vec<FluxTable>, FluxError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text);
+
+        let mut tables: Vec<FluxTable> = Vec::new();
+        for row in reader.records() {
+            let row = row.map_err(|e| FluxError::invalid(e.to_string()))?;
+            let record = match self.parse_row(&row)? {
+                Some(record) => record,
+                None => continue,
+            };
+            let result = match record.get("result") {
+                Some(FluxValue::String(result)) => result.clone(),
+                _ => DEFAULT_RESULT.to_string(),
+            };
+            let id = match record.get("table") {
+                Some(FluxValue::Long(id)) => *id,
+                _ => 0,
+            };
+            match tables.last_mut() {
+                Some(table) if !self.new_header && table.result == result && table.id == id => {
+                    table.records.push(record)
+                }
+                _ => tables.push(FluxTable {
+                    result,
+                    id,
+                    columns: self.columns.clone(),
+                    records: vec![record],
+                }),
+            }
+            self.new_header = false;
+        }
+        Ok(tables)
+    }
+
+    /// Returns the record of a data row, `None` for the other rows.
+    fn parse_row(&mut self, row: &csv::StringRecord) -> Result<Option<FluxRecord>, FluxError> {
+        if row.len() <= 1 {
+            // blank line between tables
+            return Ok(None);
+        }
+        let columns = &mut self.columns;
+        let annotation = &row[0];
+        if annotation.starts_with('#') {
+            if !self.in_annotations {
+                *columns = (1..row.len())
+                    .map(|_| FluxColumn {
+                        name: String::new(),
+                        data_type: DataType::String,
+                        group: false,
+                        default: String::new(),
+                    })
+                    .collect();
+                self.in_annotations = true;
+                self.has_header = false;
+            }
+            if row.len() - 1 != columns.len() {
+                return Err(FluxError::invalid(format!(
+                    "annotation has {} columns, expected {}",
+                    row.len() - 1,
+                    columns.len()
+                )));
+            }
+            match annotation {
+                "#datatype" => {
+                    for (column, value) in columns.iter_mut().zip(row.iter().skip(1)) {
+                        column.data_type = DataType::of(value)?;
+                    }
+                }
+                "#group" => {
+                    for (column, value) in columns.iter_mut().zip(row.iter().skip(1)) {
+                        column.group = match value {
+                            "true" => true,
+                            "false" => false,
+                            _ => {
+                                return Err(FluxError::invalid(format!(
+                                    "invalid #group value: {}",
+                                    value
+                                )))
+                            }
+                        };
+                    }
+                }
+                "#default" => {
+                    for (column, value) in columns.iter_mut().zip(row.iter().skip(1)) {
+                        column.default = value.to_string();
+                    }
+                }
+                _ => {}
+            }
+            return Ok(None);
+        }
+        if !annotation.is_empty() {
+            return Err(FluxError::invalid(format!(
+                "invalid first cell: {}",
+                annotation
+            )));
+        }
+        if row.len() - 1 != columns.len() {
+            return Err(FluxError::invalid(format!(
+                "row has {} columns, expected {}",
+                row.len() - 1,
+                columns.len()
+            )));
+        }
+        if !self.has_header {
+            for (column, name) in columns.iter_mut().zip(row.iter().skip(1)) {
+                column.name = name.to_string();
+            }
+            self.is_error = columns.first().is_some_and(|c| c.name == "error");
+            self.has_header = true;
+            self.new_header = true;
+            self.in_annotations = false;
+            return Ok(None);
+        }
+        if self.is_error {
+            let message = row
+                .get(1)
+                .filter(|m| !m.is_empty())
+                .unwrap_or("unknown query error");
+            return Err(FluxError::Query(
+                match row.get(2).filter(|r| !r.is_empty()) {
+                    Some(reference) => format!("{} ({})", message, reference),
+                    None => message.to_string(),
+                },
+            ));
+        }
+
+        let mut record = FluxRecord::new();
+        for (column, value) in columns.iter().zip(row.iter().skip(1)) {
+            let value = if value.is_empty() {
+                &column.default
+            } else {
+                value
+            };
+            record.insert(column.name.clone(), column.data_type.parse(value)?);
+        }
+        Ok(Some(record))
+    }
+}
+
+/// Parses an annotated CSV response as it arrives, in chunks that may end
+/// in the middle of a row.
+#[derive(Default)]
+pub(crate) struct StreamParser {
+    rows: RowParser,
+    buffer: Vec<u8>,
+    /// Length of the start of `buffer` already scanned for row ends.
+    scanned: usize,
+    /// Whether the end of `buffer` is inside a quoted value.
+    in_quotes: bool,
+}
+
+impl StreamParser {
+    /// Returns the records of the rows completed by `chunk`.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Result<Vec<FluxRecord>, FluxError> {
+        self.buffer.extend_from_slice(chunk);
+        let mut end = None;
+        for (i, byte) in self.buffer[self.scanned..].iter().enumerate() {
+            match byte {
+                // an escaped quote toggles twice
+                b'"' => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => end = Some(self.scanned + i + 1),
+                _ => {}
+            }
+        }
+        self.scanned = self.buffer.len();
+        match end {
+            Some(end) => {
+                let rest = self.buffer.split_off(end);
+                let rows = std::mem::replace(&mut self.buffer, rest);
+                self.scanned -= end;
+                Ok(flatten(self.rows.parse(&rows)?))
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the records of what is left once the response has ended.
+    pub(crate) fn finish(&mut self) -> Result<Vec<FluxRecord>, FluxError> {
+        if self.in_quotes {
+            return Err(FluxError::invalid("unterminated quoted value".to_string()));
+        }
+        let rows = std::mem::take(&mut self.buffer);
+        self.scanned = 0;
+        Ok(flatten(self.rows.parse(&rows)?))
+    }
+}
+
+/// Merges the one-row-per-field records of an unpivoted response into one
+/// record per series and timestamp, with each `_field` set to its `_value`.
+/// Records without a `_field` column are kept as they are.
+pub(crate) fn merge_fields(records: Vec<FluxRecord>) -> Vec<FluxRecord> {
+    let mut merged: Vec<FluxRecord> = Vec::new();
+    let mut index: BTreeMap<Vec<(String, String)>, usize> = BTreeMap::new();
+    for mut record in records {
+        let field = match record.get("_field") {
+            Some(FluxValue::String(field)) => field.clone(),
+            _ => {
+                merged.push(record);
+                continue;
+            }
+        };
+        let value = record.remove("_value").unwrap_or(FluxValue::Null);
+        record.remove("_field");
+        record.remove("table");
+        let key = record
+            .iter()
+            .map(|(k, v)| (k.clone(), v.to_string()))
+            .collect::<Vec<_>>();
+        match index.get(&key) {
+            Some(i) => {
+                merged[*i].insert(field, value);
+            }
+            None => {
+                record.insert(field, value);
+                index.insert(key, merged.len());
+                merged.push(record);
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLES: &str = include_str!("../test/flux/tables.csv");
+    const RESULTS: &str = include_str!("../test/flux/results.csv");
+    const TYPES: &str = include_str!("../test/flux/types.csv");
+    const ERROR: &str = include_str!("../test/flux/error.csv");
+    const CORPUS: &[&str] = &[TABLES, RESULTS, TYPES, ERROR];
+
+    fn shape(tables: &[FluxTable]) -> Vec<(&str, i64, usize)> {
+        tables
+            .iter()
+            .map(|table| (table.result.as_str(), table.id, table.records.len()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_tables_with_group_keys() {
+        let tables = parse_tables(TABLES).unwrap();
+        assert_eq!(
+            shape(&tables),
+            [("_result", 0, 2), ("_result", 1, 1), ("_result", 2, 1)]
+        );
+        let group_key = tables[0]
+            .columns
+            .iter()
+            .filter(|column| column.group)
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            group_key,
+            ["_start", "_stop", "_field", "_measurement", "host"]
+        );
+        assert_eq!(tables[1].records[0]["_value"], FluxValue::Double(-3.0));
+        assert_eq!(tables[2].columns[5].data_type, DataType::Long);
+        assert_eq!(tables[2].records[0]["_value"], FluxValue::Long(7));
+    }
+
+    #[test]
+    fn parses_named_results() {
+        let tables = parse_tables(RESULTS).unwrap();
+        assert_eq!(
+            shape(&tables),
+            [("min", 0, 1), ("min", 1, 1), ("max", 0, 1), ("max", 1, 1)]
+        );
+        assert_eq!(tables[2].records[0]["_value"], FluxValue::Double(2.5));
+    }
+
+    #[test]
+    fn parses_every_data_type() {
+        let records = parse_records(TYPES).unwrap();
+        let time = DateTime::parse_from_rfc3339("2024-01-01T00:00:00.123456789+02:00").unwrap();
+        let expected = [
+            (
+                "s",
+                FluxValue::String("quoted, with \"quotes\"\nand a newline".to_string()),
+            ),
+            ("d", FluxValue::Double(0.001)),
+            ("b", FluxValue::Bool(true)),
+            ("l", FluxValue::Long(i64::MIN)),
+            ("u", FluxValue::UnsignedLong(u64::MAX)),
+            ("dur", FluxValue::Duration("1h30m".to_string())),
+            ("bin", FluxValue::Base64Binary("aGVsbG8=".to_string())),
+            ("t", FluxValue::Time(time)),
+        ];
+        for (name, value) in expected {
+            assert_eq!(records[0][name], value, "column {}", name);
+        }
+        assert_eq!(records[1]["s"], FluxValue::String("fallback".to_string()));
+        for name in ["d", "l", "u", "dur", "bin", "t"] {
+            assert_eq!(records[1][name], FluxValue::Null, "column {}", name);
+        }
+    }
+
+    #[test]
+    fn reports_error_tables() {
+        match parse_records(ERROR) {
+            Err(FluxError::Query(message)) => assert_eq!(
+                message,
+                "failed to compile query: undefined identifier x (897)"
+            ),
+            other => panic!("expected a query error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_annotations() {
+        for text in [
+            "#datatype,string\n#group,maybe\n,a\n,x\n",
+            "#datatype,text\n,a\n,x\n",
+            "#datatype,string,long\n,a,b\n,x\n",
+            "#datatype,long\n,a\n,x\n",
+            "#datatype,string\n,a\nx,y\n",
+        ] {
+            assert!(
+                matches!(parse_records(text), Err(FluxError::Invalid(_))),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    const DATA_TYPES: &[(&str, DataType)] = &[
+        ("string", DataType::String),
+        ("double", DataType::Double),
+        ("boolean", DataType::Bool),
+        ("long", DataType::Long),
+        ("unsignedLong", DataType::UnsignedLong),
+        ("duration", DataType::Duration),
+        ("base64Binary", DataType::Base64Binary),
+        ("dateTime:RFC3339", DataType::Time),
+        ("dateTime:RFC3339Nano", DataType::Time),
+    ];
+
+    fn column(name: &str, data_type: DataType, group: bool, default: &str) -> FluxColumn {
+        FluxColumn {
+            name: name.to_string(),
+            data_type,
+            group,
+            default: default.to_string(),
+        }
+    }
+
+    fn generate_value(rng: &mut fastrand::Rng, data_type: DataType) -> FluxValue {
+        if rng.usize(..5) == 0 {
+            return FluxValue::Null;
+        }
+        match data_type {
+            DataType::String => FluxValue::String(
+                (0..rng.usize(1..8))
+                    .map(|_| ['a', 'Z', ' ', ',', '"', '\n', '\r', '#', 'é'][rng.usize(..9)])
+                    .collect(),
+            ),
+            DataType::Double => FluxValue::Double((rng.f64() - 0.5) * 10f64.powi(rng.i32(-5..12))),
+            DataType::Bool => FluxValue::Bool(rng.bool()),
+            DataType::Long => FluxValue::Long(rng.i64(..)),
+            DataType::UnsignedLong => FluxValue::UnsignedLong(rng.u64(..)),
+            DataType::Duration => {
+                FluxValue::Duration(["1h30m", "-5s", "0s", "1mo2w"][rng.usize(..4)].to_string())
+            }
+            DataType::Base64Binary => FluxValue::Base64Binary(rng.u64(..).to_string() + "=="),
+            DataType::Time => {
+                let offset = FixedOffset::east_opt(rng.i32(-12..=12) * 3600).unwrap();
+                let time =
+                    DateTime::from_timestamp(rng.i64(0..4_000_000_000), rng.u32(..1_000_000_000))
+                        .unwrap();
+                FluxValue::Time(time.with_timezone(&offset))
+            }
+        }
+    }
+
+    /// Tables of one or two results, with several tables sharing columns.
+    fn generate_tables(rng: &mut fastrand::Rng) -> Vec<FluxTable> {
+        let mut tables = Vec::new();
+        for result in ["_result", "max"].into_iter().take(rng.usize(1..=2)) {
+            let mut id = 0;
+            for _ in 0..rng.usize(1..=3) {
+                let mut columns = vec![
+                    column("result", DataType::String, false, result),
+                    column("table", DataType::Long, false, ""),
+                ];
+                for i in 0..rng.usize(1..=5) {
+                    let (_, data_type) = DATA_TYPES[rng.usize(..DATA_TYPES.len())];
+                    columns.push(column(&format!("c{}", i), data_type, rng.bool(), ""));
+                }
+                for _ in 0..rng.usize(1..=3) {
+                    let records = (0..rng.usize(1..=4))
+                        .map(|_| {
+                            let mut record = FluxRecord::new();
+                            record.insert(
+                                "result".to_string(),
+                                FluxValue::String(result.to_string()),
+                            );
+                            record.insert("table".to_string(), FluxValue::Long(id));
+                            for column in &columns[2..] {
+                                record.insert(
+                                    column.name.clone(),
+                                    generate_value(rng, column.data_type),
+                                );
+                            }
+                            record
+                        })
+                        .collect();
+                    tables.push(FluxTable {
+                        result: result.to_string(),
+                        id,
+                        columns: columns.clone(),
+                        records,
+                    });
+                    id += 1;
+                }
+            }
+        }
+        tables
+    }
+
+    fn render_cell(rng: &mut fastrand::Rng, value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) || rng.usize(..4) == 0 {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// Renders the tables as InfluxDB does, sharing annotations between
+    /// consecutive tables with the same columns or not.
+    fn render(rng: &mut fastrand::Rng, tables: &[FluxTable]) -> String {
+        let newline = if rng.bool() { "\r\n" } else { "\n" };
+        let mut text = String::new();
+        let mut previous: Option<&Vec<FluxColumn>> = None;
+        for table in tables {
+            if previous != Some(&table.columns) || rng.bool() {
+                if previous.is_some() {
+                    text.push_str(newline);
+                }
+                let mut datatype = String::from("#datatype");
+                let mut group = String::from("#group");
+                let mut default = String::from("#default");
+                let mut header = String::new();
+                for column in &table.columns {
+                    let literals = DATA_TYPES.iter().filter(|(_, t)| *t == column.data_type);
+                    let literals = literals.map(|(literal, _)| *literal).collect::<Vec<_>>();
+                    datatype.push_str(&format!(",{}", literals[rng.usize(..literals.len())]));
+                    group.push_str(&format!(",{}", column.group));
+                    default.push_str(&format!(",{}", column.default));
+                    header.push_str(&format!(",{}", column.name));
+                }
+                for line in [datatype, group, default, header] {
+                    text.push_str(&line);
+                    text.push_str(newline);
+                }
+            }
+            for record in &table.records {
+                for column in &table.columns {
+                    let value = match &record[&column.name] {
+                        FluxValue::Null => String::new(),
+                        FluxValue::String(v) if *v == column.default && rng.bool() => String::new(),
+                        value => value.to_string(),
+                    };
+                    text.push(',');
+                    text.push_str(&render_cell(rng, &value));
+                }
+                text.push_str(newline);
+            }
+            previous = Some(&table.columns);
+        }
+        text
+    }
+
+    fn parse_in_chunks(rng: &mut fastrand::Rng, text: &str) -> Result<Vec<FluxRecord>, FluxError> {
+        let mut parser = StreamParser::default();
+        let mut records = Vec::new();
+        let mut rest = text.as_bytes();
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rng.usize(1..=rest.len().min(64)));
+            records.extend(parser.feed(chunk)?);
+            rest = tail;
+        }
+        records.extend(parser.finish()?);
+        Ok(records)
+    }
+
+    #[test]
+    fn round_trips_generated_tables() {
+        for seed in 0..500 {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let tables = generate_tables(&mut rng);
+            let text = render(&mut rng, &tables);
+            let parsed =
+                parse_tables(&text).unwrap_or_else(|e| panic!("seed {}: {}\n{}", seed, e, text));
+            assert_eq!(parsed, tables, "seed {}:\n{}", seed, text);
+        }
+    }
+
+    #[test]
+    fn streams_in_any_chunks() {
+        for seed in 0..500 {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let text = match CORPUS.get(seed as usize % 8) {
+                Some(text) => text.to_string(),
+                None => {
+                    let tables = generate_tables(&mut rng);
+                    render(&mut rng, &tables)
+                }
+            };
+            let whole = parse_records(&text).map_err(|e| e.to_string());
+            let chunked = parse_in_chunks(&mut rng, &text).map_err(|e| e.to_string());
+            assert_eq!(chunked, whole, "seed {}:\n{}", seed, text);
+        }
+    }
+
+    #[test]
+    fn survives_mutated_input() {
+        for seed in 0..5000 {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let mut bytes = CORPUS[rng.usize(..CORPUS.len())].as_bytes().to_vec();
+            for _ in 0..rng.usize(1..8) {
+                let at = rng.usize(..=bytes.len());
+                match rng.usize(..4) {
+                    0 => bytes.insert(at, b",\"\n\r#-e0"[rng.usize(..8)]),
+                    1 => {
+                        let end = rng.usize(at..=bytes.len());
+                        bytes.drain(at..end);
+                    }
+                    2 if at < bytes.len() => bytes[at] = rng.u8(..),
+                    _ => bytes.truncate(at),
+                }
+            }
+            let text = String::from_utf8_lossy(&bytes);
+            let _ = parse_tables(&text);
+            let _ = parse_in_chunks(&mut rng, &text);
+        }
+    }
+}
@@ -1,19 +1,31 @@
 extern crate influxdb2;
 
+mod arrow;
 mod bucket;
+mod connection;
+mod duration;
 mod engine;
+mod flux;
+mod point;
+mod query;
+mod retry;
 mod schema;
 mod select;
 mod store;
+mod stream;
+mod writer;
 
 use pyo3::prelude::*;
 use schema::get_schema;
 use select::_Select;
 
+use self::arrow::_ArrowResult;
 use self::bucket::_Bucket;
 use self::engine::{create_engine, PyEngine};
-use self::select::{_Mapped, _WhereClause, _WhereOperator};
+use self::select::{_Mapped, _Ordering, _WhereClause, _WhereOperator};
 use self::store::{_Registry, _Store};
+use self::stream::_QueryStream;
+use self::writer::_BufferedWriter;
 
 #[pymodule]
 fn aluminum(_py: Python, m: &PyModule) -> PyResult<()> {
@@ -23,8 +35,12 @@ fn aluminum(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<_WhereClause>()?;
     m.add_class::<_WhereOperator>()?;
     m.add_class::<_Mapped>()?;
+    m.add_class::<_Ordering>()?;
     m.add_class::<PyEngine>()?;
     m.add_class::<_Bucket>()?;
+    m.add_class::<_BufferedWriter>()?;
+    m.add_class::<_QueryStream>()?;
+    m.add_class::<_ArrowResult>()?;
     m.add_function(wrap_pyfunction!(create_engine, m)?)?;
     m.add_function(wrap_pyfunction!(get_schema, m)?)?;
     Ok(())
@@ -0,0 +1,269 @@
+use std::io;
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use influxdb2::api::write::TimestampPrecision;
+use influxdb2::models::WriteDataPoint;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBool, PyDateTime, PyFloat, PyLong, PyString};
+
+use crate::schema::FieldType;
+
+const MEASUREMENT_DELIMITERS: &[char] = &[',', ' '];
+const KEY_DELIMITERS: &[char] = &[',', '=', ' '];
+const STRING_FIELD_DELIMITERS: &[char] = &['"', '\\'];
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FieldValue {
+    Bool(bool),
+    Float(f64),
+    Int(i64),
+    UInt(u64),
+    Str(String),
+}
+
+impl FieldValue {
+    pub(crate) fn extract(field_type: &FieldType, name: &str, value: &PyAny) -> PyResult<Self> {
+        let is_int = value.is_instance_of::<PyLong>()? && !value.is_instance_of::<PyBool>()?;
+        let out_of_range = |_| {
+            PyValueError::new_err(format!(
+                "Field '{}' is out of range for {}: {}",
+                name,
+                field_type.name(),
+                value
+            ))
+        };
+        match field_type {
+            FieldType::Int if is_int => Ok(Self::Int(value.extract().map_err(out_of_range)?)),
+            FieldType::UInt if is_int => Ok(Self::UInt(value.extract().map_err(out_of_range)?)),
+            FieldType::Float if is_int || value.is_instance_of::<PyFloat>()? => {
+                let value: f64 = value.extract()?;
+                if !value.is_finite() {
+                    return Err(PyValueError::new_err(format!(
+                        "Field '{}' must be a finite number, got {}",
+                        name, value
+                    )));
+                }
+                Ok(Self::Float(value))
+            }
+            FieldType::Bool if value.is_instance_of::<PyBool>()? => {
+                Ok(Self::Bool(value.extract()?))
+            }
+            FieldType::Str if value.is_instance_of::<PyString>()? => {
+                Ok(Self::Str(value.extract()?))
+            }
+            FieldType::Int
+            | FieldType::UInt
+            | FieldType::Float
+            | FieldType::Bool
+            | FieldType::Str => Err(PyTypeError::new_err(format!(
+                "Field '{}' expects a value of type {}, got {}",
+                name,
+                field_type.name(),
+                value.get_type().name()?
+            ))),
+            _ => Err(PyTypeError::new_err(format!(
+                "Field '{}' has unsupported type {}",
+                name,
+                field_type.name()
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Point {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, FieldValue)>,
+    timestamp: Option<i64>,
+}
+
+impl Point {
+    pub(crate) fn new(measurement: String) -> Self {
+        Self {
+            measurement,
+            tags: Vec::new(),
+            fields: Vec::new(),
+            timestamp: None,
+        }
+    }
+
+    pub(crate) fn tag(&mut self, name: String, value: String) {
+        self.tags.push((name, value));
+    }
+
+    pub(crate) fn field(&mut self, name: String, value: FieldValue) {
+        self.fields.push((name, value));
+    }
+
+    pub(crate) fn timestamp(&mut self, timestamp: i64) {
+        self.timestamp = Some(timestamp);
+    }
+
+    pub(crate) fn has_timestamp(&self) -> bool {
+        self.timestamp.is_some()
+    }
+
+    pub(crate) fn validate(mut self) -> PyResult<Self> {
+        if self.fields.is_empty() {
+            return Err(PyValueError::new_err(format!(
+                "Invalid point: measurement '{}' has no fields",
+                self.measurement
+            )));
+        }
+        // line protocol expects tags sorted by key for best write performance
+        self.tags.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(self)
+    }
+
+    pub(crate) fn line_len(&self) -> usize {
+        let mut line = Vec::new();
+        self.write_data_point_to(&mut line)
+            .map(|_| line.len())
+            .unwrap_or_default()
+    }
+}
+
+/// Splits points into batches of at most `max_points` points and `max_bytes`
+/// bytes of line protocol. A single point larger than `max_bytes` is sent alone.
+pub(crate) fn chunk_points(
+    points: Vec<Point>,
+    max_points: usize,
+    max_bytes: usize,
+) -> Vec<Vec<Point>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    for point in points {
+        let len = point.line_len();
+        if !batch.is_empty() && (batch.len() >= max_points || batch_bytes + len > max_bytes) {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch_bytes += len;
+        batch.push(point);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+impl WriteDataPoint for Point {
+    fn write_data_point_to<W>(&self, mut w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        write_escaped(&self.measurement, MEASUREMENT_DELIMITERS, &mut w)?;
+
+        for (k, v) in &self.tags {
+            w.write_all(b",")?;
+            write_escaped(k, KEY_DELIMITERS, &mut w)?;
+            w.write_all(b"=")?;
+            write_escaped(v, KEY_DELIMITERS, &mut w)?;
+        }
+
+        for (i, (k, v)) in self.fields.iter().enumerate() {
+            w.write_all(if i == 0 { b" " } else { b"," })?;
+            write_escaped(k, KEY_DELIMITERS, &mut w)?;
+            w.write_all(b"=")?;
+            match v {
+                FieldValue::Bool(v) => write!(w, "{}", v)?,
+                FieldValue::Float(v) => write!(w, "{}", v)?,
+                FieldValue::Int(v) => write!(w, "{}i", v)?,
+                FieldValue::UInt(v) => write!(w, "{}u", v)?,
+                FieldValue::Str(v) => {
+                    w.write_all(b"\"")?;
+                    write_escaped(v, STRING_FIELD_DELIMITERS, &mut w)?;
+                    w.write_all(b"\"")?;
+                }
+            }
+        }
+
+        if let Some(ts) = self.timestamp {
+            write!(w, " {}", ts)?;
+        }
+
+        w.write_all(b"\n")
+    }
+}
+
+/// Converts a `datetime`, an epoch integer already expressed in `precision`
+/// or an ISO 8601 string into an epoch timestamp in `precision`.
+/// Naive datetimes and strings without an offset are taken as UTC.
+pub(crate) fn extract_timestamp(
+    name: &str,
+    value: &PyAny,
+    precision: TimestampPrecision,
+) -> PyResult<i64> {
+    let datetime = if value.is_instance_of::<PyLong>()? && !value.is_instance_of::<PyBool>()? {
+        return value.extract();
+    } else if value.is_instance_of::<PyDateTime>()? {
+        extract_datetime(value)?
+    } else if value.is_instance_of::<PyString>()? {
+        parse_timestamp(value.extract()?).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "Timestamp '{}' is not a valid ISO 8601 string: {}",
+                name, value
+            ))
+        })?
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Timestamp '{}' expects a datetime, int or str, got {}",
+            name,
+            value.get_type().name()?
+        )));
+    };
+
+    to_epoch(&datetime, precision).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Timestamp '{}' is out of range: {}",
+            name, datetime
+        ))
+    })
+}
+
+pub(crate) fn to_epoch(datetime: &DateTime<Utc>, precision: TimestampPrecision) -> Option<i64> {
+    match precision {
+        TimestampPrecision::Seconds => Some(datetime.timestamp()),
+        TimestampPrecision::Milliseconds => Some(datetime.timestamp_millis()),
+        TimestampPrecision::Microseconds => Some(datetime.timestamp_micros()),
+        TimestampPrecision::Nanoseconds => datetime.timestamp_nanos_opt(),
+    }
+}
+
+/// pyo3's chrono conversion ignores the offset of aware datetimes,
+/// so the offset is applied here instead.
+pub(crate) fn extract_datetime(value: &PyAny) -> PyResult<DateTime<Utc>> {
+    let offset = value.call_method0("utcoffset")?;
+    if offset.is_none() {
+        return Ok(Utc.from_utc_datetime(&value.extract()?));
+    }
+    let kwargs = [("tzinfo", value.py().None())].into_py_dict(value.py());
+    let naive: NaiveDateTime = value.call_method("replace", (), Some(kwargs))?.extract()?;
+    let offset: Duration = offset.extract()?;
+    Ok(Utc.from_utc_datetime(&(naive - offset)))
+}
+
+pub(crate) fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|datetime| Utc.from_utc_datetime(&datetime))
+}
+
+fn write_escaped<W>(value: &str, delimiters: &[char], mut w: W) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let mut last = 0;
+    for (idx, delim) in value.match_indices(delimiters) {
+        write!(w, r#"{}\{}"#, &value[last..idx], delim)?;
+        last = idx + delim.len();
+    }
+    w.write_all(&value.as_bytes()[last..])
+}
@@ -0,0 +1,1163 @@
+use std::fmt;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDateTime, PyDelta, PyFloat, PyLong, PyString};
+
+use crate::duration::FluxDuration;
+use crate::point::{extract_datetime, parse_timestamp};
+use crate::schema::{Column, ColumnRole, FieldType};
+use crate::select::_WhereOperator;
+
+const PIVOT: &str = "pivot(rowKey: [\"_time\"], columnKey: [\"_field\"], valueColumn: \"_value\")";
+
+/// The parts of a Flux query, rendered in pipeline order by `to_flux()`:
+/// imports, source bucket, range, the projected fields, one `filter()` per
+/// where clause, grouping, the aggregation window, the transformations,
+/// sorting, the limit, then the projected columns. `first()` and `last()`
+/// ungroup the series before sorting, so that the limit keeps one record.
+///
+/// Models with several fields, and queries that filter or sort on a field,
+/// pivot the rows of each field into one row per timestamp. The aggregation
+/// window and the transformations need one row per field, so they come
+/// before the pivot, after the where clauses on the measurement and tags.
+/// Where clauses on fields need the pivot, so they come after it and can't
+/// be combined with an aggregation window or a moving average, unless they
+/// only read the single selected field and can filter its rows. Numeric
+/// aggregates and transformations only read the fields holding numbers.
+#[derive(Clone, Debug)]
+pub(crate) struct FluxQuery {
+    pub(crate) bucket: String,
+    pub(crate) start: TimeBound,
+    pub(crate) stop: Option<TimeBound>,
+    pub(crate) filters: Vec<Predicate>,
+    /// Columns of the group key. `_field` is appended before the pivot so
+    /// that fields are never aggregated together.
+    pub(crate) group: Option<Vec<String>>,
+    pub(crate) window: Option<AggregateWindow>,
+    pub(crate) transforms: Vec<Transform>,
+    /// Names of the fields of the measurement.
+    pub(crate) fields: Vec<String>,
+    /// Names of the fields holding numbers, the only ones read by numeric
+    /// aggregates and transformations.
+    pub(crate) numeric_fields: Vec<String>,
+    pub(crate) sort: Option<Sort>,
+    pub(crate) limit: Option<u64>,
+    pub(crate) offset: u64,
+    /// The record kept by `first()` or `last()`, a single one of the whole
+    /// result or of each group instead of one per series.
+    pub(crate) pick: Option<Pick>,
+    pub(crate) projection: Option<Projection>,
+}
+
+impl FluxQuery {
+    pub(crate) fn new(bucket: String) -> Self {
+        Self {
+            bucket,
+            start: TimeBound::Relative(FluxDuration::from_nanos(-3_600_000_000_000)),
+            stop: None,
+            filters: Vec::new(),
+            group: None,
+            window: None,
+            transforms: Vec::new(),
+            fields: Vec::new(),
+            numeric_fields: Vec::new(),
+            sort: None,
+            limit: None,
+            offset: 0,
+            pick: None,
+            projection: None,
+        }
+    }
+
+    pub(crate) fn to_flux(&self) -> PyResult<String> {
+        let mut flux = String::new();
+        if self.filters.iter().any(Predicate::uses_strings) {
+            flux.push_str("import \"strings\"\n");
+        }
+        flux.push_str(&format!(
+            "from(bucket: {})",
+            FluxLiteral::String(self.bucket.clone())
+        ));
+        flux.push_str(&format!(" |> range(start: {}", self.start));
+        if let Some(stop) = &self.stop {
+            flux.push_str(&format!(", stop: {}", stop));
+        }
+        flux.push(')');
+        if let Some(projection) = self.projection.as_ref().filter(|p| !p.fields.is_empty()) {
+            // fields used by the where clauses and the sort are read too,
+            // the final keep() drops them
+            let mut fields: Vec<&str> = projection.fields.iter().map(String::as_str).collect();
+            for field in self.referenced_fields() {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+            flux.push_str(&format!(
+                " |> filter(fn: (r) => {})",
+                field_membership(fields)
+            ));
+        } else {
+            // the columns added by elapsed() replace the fields stored under
+            // their name
+            let stored: Vec<_> = self
+                .transforms
+                .iter()
+                .filter_map(Transform::elapsed_column)
+                .map(|column| {
+                    Predicate::Compare(Comparison {
+                        column: "_field".to_string(),
+                        operator: _WhereOperator::NE,
+                        value: FluxLiteral::String(column.to_string()),
+                    })
+                })
+                .collect();
+            if !stored.is_empty() {
+                flux.push_str(&format!(
+                    " |> filter(fn: (r) => {})",
+                    Predicate::And(stored)
+                ));
+            }
+        }
+        let mut filtered = Vec::new();
+        for filter in &self.filters {
+            filter.columns(&mut filtered);
+        }
+        // the where clauses run before elapsed() adds its column
+        let mut elapsed = self.transforms.iter().filter_map(Transform::elapsed_column);
+        if let Some(column) = elapsed.find(|column| filtered.contains(column)) {
+            return Err(PyValueError::new_err(format!(
+                "Where clauses can't use the elapsed() column '{}'",
+                column
+            )));
+        }
+        let pivot = self.fields.len() > 1 || !self.referenced_fields().is_empty();
+        let (field_filters, filters): (Vec<_>, Vec<_>) = self
+            .filters
+            .iter()
+            .partition(|filter| self.reads_fields(filter));
+        for filter in filters {
+            flux.push_str(&format!(" |> filter(fn: (r) => {})", filter));
+        }
+        // the aggregation window and moving averages need the rows of each
+        // field, which where clauses on the only selected field can filter
+        let per_field = self.window.is_some() || self.transforms.iter().any(Transform::value_only);
+        let field_filters = match self.selected_fields() {
+            [field] if per_field && field_filters.iter().all(|f| self.reads_only(f, field)) => {
+                for filter in field_filters {
+                    let filter = Predicate::And(vec![
+                        Predicate::Compare(Comparison {
+                            column: "_field".to_string(),
+                            operator: _WhereOperator::EQ,
+                            value: FluxLiteral::String(field.clone()),
+                        }),
+                        filter.renamed(field, "_value"),
+                    ]);
+                    flux.push_str(&format!(" |> filter(fn: (r) => {})", filter));
+                }
+                Vec::new()
+            }
+            _ => field_filters,
+        };
+        if field_filters.is_empty() {
+            // each field is aggregated and transformed as `_value`, before
+            // the pivot; elapsed() adds a column, which the pivot would drop
+            let numeric = match &self.window {
+                Some(window) if window.function.numeric() => Some(window.function.value()),
+                _ => self
+                    .transforms
+                    .iter()
+                    .find(|transform| transform.numeric())
+                    .map(Transform::function),
+            };
+            if let Some(function) = numeric {
+                if let Some(fields) = self.numeric_selection(function)? {
+                    flux.push_str(&format!(
+                        " |> filter(fn: (r) => {})",
+                        field_membership(fields)
+                    ));
+                }
+            }
+            self.push_group(&mut flux, true);
+            if let Some(window) = &self.window {
+                flux.push_str(&format!(" |> {}", window));
+            }
+            let (elapsed, transforms): (Vec<_>, Vec<_>) = self
+                .transforms
+                .iter()
+                .partition(|transform| matches!(transform, Transform::Elapsed { .. }));
+            for transform in transforms {
+                flux.push_str(&format!(" |> {}", transform.render(None)));
+            }
+            if pivot {
+                flux.push_str(&format!(" |> {}", PIVOT));
+            }
+            for transform in elapsed {
+                flux.push_str(&format!(" |> {}", transform.render(None)));
+            }
+        } else {
+            // where clauses on fields need the pivoted rows, which can no
+            // longer be aggregated per field
+            if self.window.is_some() {
+                return Err(PyValueError::new_err(
+                    "aggregate_window() can only be combined with where clauses on the single selected field",
+                ));
+            }
+            if self.transforms.iter().any(Transform::value_only) {
+                return Err(PyValueError::new_err(
+                    "Moving averages can only be combined with where clauses on the single selected field",
+                ));
+            }
+            flux.push_str(&format!(" |> {}", PIVOT));
+            for filter in field_filters {
+                flux.push_str(&format!(" |> filter(fn: (r) => {})", filter));
+            }
+            self.push_group(&mut flux, false);
+            // pivoted fields are transformed as columns, the selected ones only
+            for transform in &self.transforms {
+                let numeric = match transform.numeric() {
+                    true => self.numeric_selection(transform.function())?,
+                    false => None,
+                };
+                let fields = numeric
+                    .unwrap_or_else(|| self.selected_fields().iter().map(String::as_str).collect());
+                flux.push_str(&format!(" |> {}", transform.render(Some(&fields))));
+            }
+        }
+        // grouped selects already have one table per group
+        if self.pick.is_some() && self.group.is_none() {
+            flux.push_str(" |> group()");
+        }
+        // last() keeps the end of the final sort order, so that order_by()
+        // can come before or after it
+        let sort = match self.pick {
+            Some(pick) => {
+                let sort = self.sort.clone().unwrap_or_else(|| Sort {
+                    columns: vec!["_time".to_string()],
+                    desc: false,
+                });
+                Some(Sort {
+                    desc: sort.desc != (pick == Pick::Last),
+                    ..sort
+                })
+            }
+            None => self.sort.clone(),
+        };
+        if let Some(sort) = &sort {
+            flux.push_str(&format!(
+                " |> sort(columns: [{}], desc: {})",
+                columns_literal(&sort.columns),
+                sort.desc
+            ));
+        }
+        match (self.limit, self.offset) {
+            (Some(n), 0) => flux.push_str(&format!(" |> limit(n: {})", n)),
+            (Some(n), offset) => flux.push_str(&format!(" |> limit(n: {}, offset: {})", n, offset)),
+            (None, 0) => {}
+            (None, _) => return Err(PyValueError::new_err("offset() needs a limit()")),
+        }
+        if let Some(projection) = &self.projection {
+            // unpivoted fields are one `_field` and `_value` per row, group
+            // key columns are kept so that groups can be told apart
+            let mut columns: Vec<&str> = vec!["_time"];
+            match pivot {
+                true => columns.extend(projection.fields.iter().map(String::as_str)),
+                false => columns.extend(["_field", "_value"]),
+            }
+            for column in projection.columns.iter().chain(self.group.iter().flatten()) {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+            flux.push_str(&format!(
+                " |> keep(columns: [{}])",
+                columns_literal(&columns)
+            ));
+        }
+        Ok(flux)
+    }
+
+    /// The fields used as columns by the where clauses and the sort.
+    fn referenced_fields(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        for filter in &self.filters {
+            filter.columns(&mut columns);
+        }
+        columns.extend(
+            self.sort
+                .iter()
+                .flat_map(|sort| &sort.columns)
+                .map(String::as_str),
+        );
+        columns.retain(|column| self.fields.iter().any(|field| field == column));
+        columns
+    }
+
+    /// The fields kept by the projection, every field when it has none.
+    fn selected_fields(&self) -> &[String] {
+        match &self.projection {
+            Some(projection) if !projection.fields.is_empty() => &projection.fields,
+            _ => &self.fields,
+        }
+    }
+
+    /// The selected fields that `function` can read, `None` when they all
+    /// hold numbers. Fields selected by name must all hold numbers, others
+    /// are left out.
+    fn numeric_selection(&self, function: &str) -> PyResult<Option<Vec<&str>>> {
+        let fields = self.selected_fields();
+        let numeric = |field: &&String| self.numeric_fields.contains(field);
+        let other = match fields.iter().find(|field| !numeric(field)) {
+            Some(other) => other,
+            None => return Ok(None),
+        };
+        if self.projection.iter().any(|p| !p.fields.is_empty()) {
+            return Err(PyTypeError::new_err(format!(
+                "{}() needs numeric fields, '{}' isn't numeric",
+                function, other
+            )));
+        }
+        let fields: Vec<&str> = fields.iter().filter(numeric).map(String::as_str).collect();
+        if fields.is_empty() {
+            return Err(PyTypeError::new_err(format!(
+                "{}() needs numeric fields, {} has none",
+                function, self.bucket
+            )));
+        }
+        Ok(Some(fields))
+    }
+
+    /// Whether `field` is the only field read by `filter`.
+    fn reads_only(&self, filter: &Predicate, field: &str) -> bool {
+        let mut columns = Vec::new();
+        filter.columns(&mut columns);
+        columns
+            .iter()
+            .all(|column| *column == field || !self.fields.iter().any(|f| f == column))
+    }
+
+    fn reads_fields(&self, filter: &Predicate) -> bool {
+        let mut columns = Vec::new();
+        filter.columns(&mut columns);
+        columns
+            .iter()
+            .any(|column| self.fields.iter().any(|field| field == column))
+    }
+
+    fn push_group(&self, flux: &mut String, by_field: bool) {
+        if let Some(group) = &self.group {
+            let mut columns = group.iter().map(String::as_str).collect::<Vec<_>>();
+            if by_field {
+                columns.push("_field");
+            }
+            flux.push_str(&format!(
+                " |> group(columns: [{}])",
+                columns_literal(&columns)
+            ));
+        }
+    }
+}
+
+/// A `_field` membership test keeping the rows of `fields`.
+fn field_membership(fields: Vec<&str>) -> Predicate {
+    Predicate::In(Membership {
+        column: "_field".to_string(),
+        values: fields
+            .into_iter()
+            .map(|field| FluxLiteral::String(field.to_string()))
+            .collect(),
+    })
+}
+
+fn columns_literal<S: AsRef<str>>(columns: &[S]) -> String {
+    columns
+        .iter()
+        .map(|column| FluxLiteral::String(column.as_ref().to_string()).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The columns kept by a select of model columns.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Projection {
+    /// Result columns of the selected measurement, tags and timestamp.
+    pub(crate) columns: Vec<String>,
+    /// Names of the selected fields. When empty, every field is kept until
+    /// the pivot, and none after it.
+    pub(crate) fields: Vec<String>,
+}
+
+/// A `sort()` call. Flux sorts every column in the same direction.
+#[derive(Clone, Debug)]
+pub(crate) struct Sort {
+    pub(crate) columns: Vec<String>,
+    pub(crate) desc: bool,
+}
+
+/// The end of the sort order kept by `first()` or `last()`, in time order
+/// unless sorted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pick {
+    First,
+    Last,
+}
+
+/// A Flux aggregate function usable as the `fn` of `aggregateWindow()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Aggregate {
+    Mean,
+    Sum,
+    Count,
+    Min,
+    Max,
+    Median,
+    First,
+    Last,
+}
+
+impl Aggregate {
+    const ALL: [Aggregate; 8] = [
+        Aggregate::Mean,
+        Aggregate::Sum,
+        Aggregate::Count,
+        Aggregate::Min,
+        Aggregate::Max,
+        Aggregate::Median,
+        Aggregate::First,
+        Aggregate::Last,
+    ];
+
+    pub(crate) fn of(value: &str) -> PyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|aggregate| aggregate.value() == value)
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Invalid aggregate function: {}, expected one of {}",
+                    value,
+                    Self::ALL.map(|aggregate| aggregate.value()).join(", ")
+                ))
+            })
+    }
+
+    pub(crate) fn value(&self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Sum => "sum",
+            Aggregate::Count => "count",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::Median => "median",
+            Aggregate::First => "first",
+            Aggregate::Last => "last",
+        }
+    }
+
+    /// Whether the function only aggregates numbers.
+    pub(crate) fn numeric(&self) -> bool {
+        matches!(
+            self,
+            Aggregate::Mean | Aggregate::Sum | Aggregate::Median | Aggregate::Min | Aggregate::Max
+        )
+    }
+
+    /// The type of the aggregated values of a field declared as `field_type`:
+    /// `count` counts any type into an int, `mean` and `median` turn numbers
+    /// into floats, the others select or add values of the declared type.
+    pub(crate) fn result_type(&self, field_type: &FieldType) -> FieldType {
+        match (self, field_type) {
+            (Aggregate::Count, _) => FieldType::Int,
+            (
+                Aggregate::Mean | Aggregate::Median,
+                FieldType::Int | FieldType::UInt | FieldType::Float,
+            ) => FieldType::Float,
+            _ => field_type.clone(),
+        }
+    }
+}
+
+/// An `aggregateWindow()` call. The aggregated records keep the stop time
+/// of their window as `_time`.
+#[derive(Clone, Debug)]
+pub(crate) struct AggregateWindow {
+    pub(crate) every: FluxDuration,
+    pub(crate) function: Aggregate,
+    pub(crate) create_empty: bool,
+}
+
+impl AggregateWindow {
+    pub(crate) fn new(
+        every: FluxDuration,
+        function: Aggregate,
+        create_empty: bool,
+    ) -> PyResult<Self> {
+        if every.to_string().starts_with('-') || every.nanos() == Some(0) {
+            return Err(PyValueError::new_err(format!(
+                "Window duration must be positive, got {}",
+                every
+            )));
+        }
+        Ok(Self {
+            every,
+            function,
+            create_empty,
+        })
+    }
+}
+
+impl fmt::Display for AggregateWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "aggregateWindow(every: {}, fn: {}, createEmpty: {})",
+            self.every,
+            self.function.value(),
+            self.create_empty
+        )
+    }
+}
+
+/// A transformation of the values of each series, applied in order after
+/// the filters. Pivoted queries transform every field column.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Transform {
+    Derivative {
+        unit: FluxDuration,
+        non_negative: bool,
+    },
+    Difference {
+        non_negative: bool,
+    },
+    MovingAverage {
+        n: u64,
+    },
+    ExponentialMovingAverage {
+        n: u64,
+    },
+    CumulativeSum,
+    /// Adds the time since the previous record, in `unit`, as `column`.
+    Elapsed {
+        unit: FluxDuration,
+        column: String,
+    },
+}
+
+impl Transform {
+    /// Checks that `unit` is a positive duration of fixed length.
+    pub(crate) fn unit(name: &str, unit: FluxDuration) -> PyResult<FluxDuration> {
+        match unit.nanos() {
+            Some(nanos) if nanos > 0 => Ok(unit),
+            _ => Err(PyValueError::new_err(format!(
+                "{}() unit must be a positive fixed duration, got {}",
+                name, unit
+            ))),
+        }
+    }
+
+    /// Checks that the number of points `n` is positive.
+    pub(crate) fn points(name: &str, n: i64) -> PyResult<u64> {
+        match u64::try_from(n) {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(PyValueError::new_err(format!(
+                "{}() needs a positive number of points, got {}",
+                name, n
+            ))),
+        }
+    }
+
+    /// The column added by `elapsed()`.
+    pub(crate) fn elapsed_column(&self) -> Option<&str> {
+        match self {
+            Transform::Elapsed { column, .. } => Some(column),
+            _ => None,
+        }
+    }
+
+    /// Whether the function transforms numbers, rather than adding a column.
+    pub(crate) fn numeric(&self) -> bool {
+        !matches!(self, Transform::Elapsed { .. })
+    }
+
+    /// Whether the function only transforms `_value`, so that it can't run
+    /// on the field columns of a pivoted query.
+    pub(crate) fn value_only(&self) -> bool {
+        matches!(
+            self,
+            Transform::MovingAverage { .. } | Transform::ExponentialMovingAverage { .. }
+        )
+    }
+
+    /// The type of the transformed values of a field declared as
+    /// `field_type`: rates and averages of numbers are floats, differences
+    /// of unsigned integers can be negative.
+    pub(crate) fn result_type(&self, field_type: &FieldType) -> FieldType {
+        match (self, field_type) {
+            (
+                Transform::Derivative { .. }
+                | Transform::MovingAverage { .. }
+                | Transform::ExponentialMovingAverage { .. },
+                FieldType::Int | FieldType::UInt | FieldType::Float,
+            ) => FieldType::Float,
+            (Transform::Difference { .. }, FieldType::UInt) => FieldType::Int,
+            _ => field_type.clone(),
+        }
+    }
+
+    fn function(&self) -> &'static str {
+        match self {
+            Transform::Derivative { .. } => "derivative",
+            Transform::Difference { .. } => "difference",
+            Transform::MovingAverage { .. } => "movingAverage",
+            Transform::ExponentialMovingAverage { .. } => "exponentialMovingAverage",
+            Transform::CumulativeSum => "cumulativeSum",
+            Transform::Elapsed { .. } => "elapsed",
+        }
+    }
+
+    /// Renders the call on `columns`, or on `_value` when `None`.
+    fn render(&self, columns: Option<&[&str]>) -> String {
+        let mut args = match self {
+            Transform::Derivative { unit, non_negative } => vec![
+                format!("unit: {}", unit),
+                format!("nonNegative: {}", non_negative),
+            ],
+            Transform::Difference { non_negative } => {
+                vec![format!("nonNegative: {}", non_negative)]
+            }
+            Transform::MovingAverage { n } | Transform::ExponentialMovingAverage { n } => {
+                vec![format!("n: {}", n)]
+            }
+            Transform::CumulativeSum => Vec::new(),
+            Transform::Elapsed { unit, column } => vec![
+                format!("unit: {}", unit),
+                format!("columnName: {}", FluxLiteral::String(column.clone())),
+            ],
+        };
+        if let Some(columns) = columns.filter(|_| self.numeric() && !self.value_only()) {
+            args.push(format!("columns: [{}]", columns_literal(columns)));
+        }
+        format!("{}({})", self.function(), args.join(", "))
+    }
+}
+
+/// How the operands of `and` and `or` are joined, `or` binding loosest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Connective {
+    Or,
+    And,
+}
+
+/// A boolean combination of clauses, rendered with the fewest parentheses:
+/// an operand is only wrapped when it binds looser than its parent.
+pub(crate) trait BooleanExpr: fmt::Display + Sized {
+    /// The connective joining the operands, `None` for a single clause.
+    fn connective(&self) -> Option<Connective>;
+
+    fn precedence(&self) -> u8 {
+        match self.connective() {
+            Some(Connective::Or) => 1,
+            Some(Connective::And) => 2,
+            None => 3,
+        }
+    }
+
+    fn join(&self, f: &mut fmt::Formatter<'_>, operands: &[Self]) -> fmt::Result {
+        let separator = match self.connective() {
+            Some(Connective::Or) => " or ",
+            Some(Connective::And) | None => " and ",
+        };
+        for (i, operand) in operands.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            if operand.precedence() < self.precedence() {
+                write!(f, "({})", operand)?;
+            } else {
+                write!(f, "{}", operand)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A filter predicate, rendered with the fewest parentheses Flux needs.
+#[derive(Clone, Debug)]
+pub(crate) enum Predicate {
+    Compare(Comparison),
+    In(Membership),
+    Match(StringMatch),
+    Exists(String),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Appends the columns the predicate reads to `columns`.
+    fn columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Predicate::Compare(comparison) => columns.push(&comparison.column),
+            Predicate::In(membership) => columns.push(&membership.column),
+            Predicate::Match(m) => columns.push(&m.column),
+            Predicate::Exists(column) => columns.push(column),
+            Predicate::And(operands) | Predicate::Or(operands) => {
+                for operand in operands {
+                    operand.columns(columns);
+                }
+            }
+            Predicate::Not(operand) => operand.columns(columns),
+        }
+    }
+
+    /// The predicate reading the column `to` instead of `from`.
+    fn renamed(&self, from: &str, to: &str) -> Predicate {
+        let rename = |column: &String| match column == from {
+            true => to.to_string(),
+            false => column.clone(),
+        };
+        match self {
+            Predicate::Compare(comparison) => Predicate::Compare(Comparison {
+                column: rename(&comparison.column),
+                ..comparison.clone()
+            }),
+            Predicate::In(membership) => Predicate::In(Membership {
+                column: rename(&membership.column),
+                ..membership.clone()
+            }),
+            Predicate::Match(m) => Predicate::Match(StringMatch {
+                column: rename(&m.column),
+                ..m.clone()
+            }),
+            Predicate::Exists(column) => Predicate::Exists(rename(column)),
+            Predicate::And(operands) => {
+                Predicate::And(operands.iter().map(|o| o.renamed(from, to)).collect())
+            }
+            Predicate::Or(operands) => {
+                Predicate::Or(operands.iter().map(|o| o.renamed(from, to)).collect())
+            }
+            Predicate::Not(operand) => Predicate::Not(Box::new(operand.renamed(from, to))),
+        }
+    }
+
+    fn uses_strings(&self) -> bool {
+        match self {
+            Predicate::Match(m) => !matches!(
+                m.operator,
+                MatchOperator::Matches | MatchOperator::NotMatches
+            ),
+            Predicate::And(operands) | Predicate::Or(operands) => {
+                operands.iter().any(Self::uses_strings)
+            }
+            Predicate::Not(operand) => operand.uses_strings(),
+            Predicate::Compare(_) | Predicate::In(_) | Predicate::Exists(_) => false,
+        }
+    }
+}
+
+impl BooleanExpr for Predicate {
+    fn connective(&self) -> Option<Connective> {
+        match self {
+            Predicate::Or(_) => Some(Connective::Or),
+            Predicate::And(_) => Some(Connective::And),
+            Predicate::Not(_)
+            | Predicate::Compare(_)
+            | Predicate::In(_)
+            | Predicate::Match(_)
+            | Predicate::Exists(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::Compare(comparison) => write!(f, "{}", comparison),
+            Predicate::In(membership) => write!(f, "{}", membership),
+            Predicate::Match(m) => write!(f, "{}", m),
+            Predicate::Exists(column) => write!(f, "exists {}", ColumnRef(column)),
+            Predicate::And(operands) | Predicate::Or(operands) => self.join(f, operands),
+            Predicate::Not(operand) => match **operand {
+                Predicate::Exists(_) => write!(f, "not {}", operand),
+                // `not` binds looser than comparisons, the parentheses only help reading
+                _ => write!(f, "not ({})", operand),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Comparison {
+    pub(crate) column: String,
+    pub(crate) operator: _WhereOperator,
+    pub(crate) value: FluxLiteral,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            ColumnRef(&self.column),
+            self.operator.value(),
+            self.value
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Membership {
+    pub(crate) column: String,
+    pub(crate) values: Vec<FluxLiteral>,
+}
+
+impl fmt::Display for Membership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Flux can't infer the type of an empty array
+        if self.values.is_empty() {
+            return f.write_str("false");
+        }
+        write!(f, "contains(value: {}, set: [", ColumnRef(&self.column))?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        f.write_str("])")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum MatchOperator {
+    Matches,
+    NotMatches,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+impl MatchOperator {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            MatchOperator::Matches => "matches",
+            MatchOperator::NotMatches => "not_matches",
+            MatchOperator::StartsWith => "startswith",
+            MatchOperator::EndsWith => "endswith",
+            MatchOperator::Contains => "contains",
+        }
+    }
+}
+
+/// A string column matched against a regular expression or a substring.
+#[derive(Clone, Debug)]
+pub(crate) struct StringMatch {
+    pub(crate) column: String,
+    pub(crate) operator: MatchOperator,
+    pub(crate) pattern: String,
+}
+
+impl StringMatch {
+    /// Checks that the pattern of a regex operator compiles, so a typo fails
+    /// before the query is sent. Flux regexes use the RE2 syntax, which the
+    /// `regex` crate follows.
+    pub(crate) fn validate(&self) -> PyResult<()> {
+        if matches!(
+            self.operator,
+            MatchOperator::Matches | MatchOperator::NotMatches
+        ) {
+            regex::Regex::new(&self.pattern).map_err(|e| {
+                PyValueError::new_err(format!("Invalid regex for '{}': {}", self.column, e))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write_regex(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("/")?;
+        let mut chars = self.pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    f.write_str("\\")?;
+                    if let Some(c) = chars.next() {
+                        write!(f, "{}", c)?;
+                    }
+                }
+                '/' => f.write_str("\\/")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("/")
+    }
+}
+
+impl fmt::Display for StringMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = ColumnRef(&self.column);
+        let pattern = FluxLiteral::String(self.pattern.clone());
+        match self.operator {
+            MatchOperator::Matches | MatchOperator::NotMatches => {
+                let operator = match self.operator {
+                    MatchOperator::Matches => "=~",
+                    _ => "!~",
+                };
+                write!(f, "{} {} ", column, operator)?;
+                self.write_regex(f)
+            }
+            MatchOperator::StartsWith => {
+                write!(f, "strings.hasPrefix(v: {}, prefix: {})", column, pattern)
+            }
+            MatchOperator::EndsWith => {
+                write!(f, "strings.hasSuffix(v: {}, suffix: {})", column, pattern)
+            }
+            MatchOperator::Contains => {
+                write!(f, "strings.containsStr(v: {}, substr: {})", column, pattern)
+            }
+        }
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "and", "builtin", "else", "empty", "exists", "if", "import", "not", "option", "or", "package",
+    "return", "test", "testcase", "then",
+];
+
+/// A reference to a column of the record `r`, as `r.name` when `name` is a
+/// plain identifier and `r["name"]` otherwise.
+pub(crate) struct ColumnRef<'a>(pub(crate) &'a str);
+
+impl ColumnRef<'_> {
+    pub(crate) fn validate(name: &str) -> PyResult<()> {
+        if name.is_empty() {
+            return Err(PyValueError::new_err("Invalid column name: empty"));
+        }
+        Ok(())
+    }
+
+    fn is_identifier(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !KEYWORDS.contains(&name)
+    }
+}
+
+impl fmt::Display for ColumnRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if Self::is_identifier(self.0) {
+            write!(f, "r.{}", self.0)
+        } else {
+            write!(f, "r[{}]", FluxLiteral::String(self.0.to_string()))
+        }
+    }
+}
+
+/// A value rendered with Flux literal syntax.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FluxLiteral {
+    String(String),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    Duration(FluxDuration),
+    Time(DateTime<Utc>),
+}
+
+impl FluxLiteral {
+    /// Accepts a `str`, `int`, `float`, `bool`, `datetime` or `timedelta`.
+    pub(crate) fn extract(name: &str, value: &PyAny) -> PyResult<Self> {
+        if value.is_instance_of::<PyBool>()? {
+            Ok(Self::Bool(value.extract()?))
+        } else if value.is_instance_of::<PyLong>()? {
+            match value.extract::<i64>() {
+                Ok(v) => Ok(Self::Int(v)),
+                Err(_) => Ok(Self::UInt(value.extract().map_err(|_| {
+                    PyValueError::new_err(format!(
+                        "Value for '{}' is out of range: {}",
+                        name, value
+                    ))
+                })?)),
+            }
+        } else if value.is_instance_of::<PyFloat>()? {
+            let v: f64 = value.extract()?;
+            if !v.is_finite() {
+                return Err(PyValueError::new_err(format!(
+                    "Value for '{}' must be a finite number, got {}",
+                    name, v
+                )));
+            }
+            Ok(Self::Float(v))
+        } else if value.is_instance_of::<PyString>()? {
+            Ok(Self::String(value.extract()?))
+        } else if value.is_instance_of::<PyDateTime>()? {
+            Ok(Self::Time(extract_datetime(value)?))
+        } else if value.is_instance_of::<PyDelta>()? {
+            Ok(Self::Duration(FluxDuration::extract(name, value)?))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "Value for '{}' has unsupported type {}",
+                name,
+                value.get_type().name()?
+            )))
+        }
+    }
+
+    /// Extracts `value` as a literal of the type declared by `column`.
+    /// Measurements and tags are stored as strings, so they compare with
+    /// the `str()` of a value of the declared type.
+    pub(crate) fn for_column(name: &str, column: &Column, value: &PyAny) -> PyResult<Self> {
+        if value.is_none() {
+            return Err(PyTypeError::new_err(format!(
+                "Value for '{}' can't be None",
+                name
+            )));
+        }
+        let literal = Self::extract(name, value)?;
+        let mismatch = || -> PyResult<Self> {
+            Err(PyTypeError::new_err(format!(
+                "Column '{}' expects a value of type {}, got {}",
+                name,
+                column.field_type.name(),
+                value.get_type().name()?
+            )))
+        };
+        if column.role == ColumnRole::Timestamp {
+            return match literal {
+                Self::Time(_) => Ok(literal),
+                Self::String(v) => parse_timestamp(&v).map(Self::Time).ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "Timestamp '{}' is not a valid ISO 8601 string: {}",
+                        name, v
+                    ))
+                }),
+                _ => Err(PyTypeError::new_err(format!(
+                    "Timestamp '{}' expects a datetime or str, got {}",
+                    name,
+                    value.get_type().name()?
+                ))),
+            };
+        }
+        let literal = match (&column.field_type, literal) {
+            (FieldType::Int, literal @ Self::Int(_)) => literal,
+            (FieldType::UInt, Self::Int(v)) if v >= 0 => Self::UInt(v as u64),
+            (FieldType::UInt, literal @ Self::UInt(_)) => literal,
+            (FieldType::Int, Self::UInt(_)) | (FieldType::UInt, Self::Int(_)) => {
+                return Err(PyValueError::new_err(format!(
+                    "Value for '{}' is out of range for {}: {}",
+                    name,
+                    column.field_type.name(),
+                    value
+                )))
+            }
+            (FieldType::Float, Self::Int(v)) => Self::Float(v as f64),
+            (FieldType::Float, Self::UInt(v)) => Self::Float(v as f64),
+            (FieldType::Float, literal @ Self::Float(_)) => literal,
+            (FieldType::Bool, literal @ Self::Bool(_)) => literal,
+            (FieldType::Str, literal @ Self::String(_)) => literal,
+            (FieldType::DateTime, literal @ Self::Time(_)) => literal,
+            _ => return mismatch(),
+        };
+        match column.role {
+            ColumnRole::Measurement | ColumnRole::Tag => Ok(Self::String(value.str()?.to_string())),
+            _ => Ok(literal),
+        }
+    }
+}
+
+impl fmt::Display for FluxLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(v) => {
+                f.write_str("\"")?;
+                let mut chars = v.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        // `${` starts an interpolation
+                        '$' if chars.peek() == Some(&'{') => f.write_str("\\$")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            Self::Int(v) => write!(f, "{}", v),
+            Self::UInt(v) => write!(f, "uint(v: \"{}\")", v),
+            Self::Float(v) if v.fract() == 0.0 => write!(f, "{:.1}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Duration(v) => write!(f, "{}", v),
+            Self::Time(v) => write!(f, "{}", v.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        }
+    }
+}
+
+/// A `range()` bound, either relative to now or an absolute time.
+#[derive(Clone, Debug)]
+pub(crate) enum TimeBound {
+    Relative(FluxDuration),
+    Absolute(DateTime<Utc>),
+}
+
+impl TimeBound {
+    /// Accepts a `datetime`, a `timedelta` or duration string relative to now,
+    /// or an ISO 8601 string.
+    pub(crate) fn extract(name: &str, value: &PyAny) -> PyResult<Self> {
+        if value.is_instance_of::<PyDateTime>()? {
+            return Ok(Self::Absolute(extract_datetime(value)?));
+        }
+        if value.is_instance_of::<PyDelta>()? {
+            return Ok(Self::Relative(FluxDuration::extract(name, value)?));
+        }
+        if value.is_instance_of::<PyString>()? {
+            let literal: &str = value.extract()?;
+            if let Some(duration) = FluxDuration::parse(literal) {
+                return Ok(Self::Relative(duration));
+            }
+            return parse_timestamp(literal).map(Self::Absolute).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Range {} expects a duration such as \"-1h\" or an ISO 8601 time, got \"{}\"",
+                    name, literal
+                ))
+            });
+        }
+        Err(PyTypeError::new_err(format!(
+            "Range {} expects a datetime, timedelta or str, got {}",
+            name,
+            value.get_type().name()?
+        )))
+    }
+
+    /// Whether `self` is before `other`, assumed true when it can't be known
+    /// without the server's clock or calendar.
+    pub(crate) fn is_before(&self, other: &TimeBound) -> bool {
+        match (self, other) {
+            (Self::Absolute(a), Self::Absolute(b)) => a < b,
+            (Self::Relative(a), Self::Relative(b)) => match (a.nanos(), b.nanos()) {
+                (Some(a), Some(b)) => a < b,
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for TimeBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Relative(duration) => write!(f, "{}", duration),
+            Self::Absolute(time) => {
+                write!(f, "{}", time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::future::Future;
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use reqwest::StatusCode;
+
+#[derive(Clone, Debug, FromPyObject)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_backoff: f64,
+    pub(crate) max_backoff: f64,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: bool,
+}
+
+impl RetryPolicy {
+    /// A single attempt, used when the engine has no retry policy.
+    pub(crate) fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: 0.0,
+            max_backoff: 0.0,
+            multiplier: 1.0,
+            jitter: false,
+        }
+    }
+
+    pub(crate) fn validate(self) -> PyResult<Self> {
+        if self.max_attempts == 0 {
+            return Err(PyValueError::new_err("max_attempts must be at least 1"));
+        }
+        // backoffs are slept as a `Duration`, which has a maximum
+        let is_duration = |value: f64| Duration::try_from_secs_f64(value).is_ok();
+        if !is_duration(self.initial_backoff) || !is_duration(self.max_backoff) {
+            return Err(PyValueError::new_err(
+                "backoff must be a finite, non negative number of seconds",
+            ));
+        }
+        if !(self.multiplier.is_finite() && self.multiplier >= 1.0) {
+            return Err(PyValueError::new_err("multiplier must be at least 1"));
+        }
+        Ok(self)
+    }
+
+    /// The delay before the retry following `attempt` (starting at 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self.initial_backoff * self.multiplier.powi(attempt as i32 - 1);
+        let backoff = backoff.min(self.max_backoff);
+        let backoff = if self.jitter {
+            backoff / 2.0 + fastrand::f64() * backoff / 2.0
+        } else {
+            backoff
+        };
+        Duration::from_secs_f64(backoff)
+    }
+
+    /// The delay before the retry following `attempt`: the one asked for by
+    /// the server, up to `max_backoff`, or the exponential backoff.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(Duration::from_secs_f64(self.max_backoff)),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+pub(crate) trait Transient {
+    /// Whether the failed request is worth retrying.
+    fn is_transient(&self) -> bool;
+
+    /// The delay asked for by the server through `Retry-After`, if any.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+pub(crate) fn is_transient_reqwest(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Runs `request` until it succeeds, fails with a non transient error or
+/// `policy.max_attempts` is reached, sleeping between attempts.
+pub(crate) async fn with_retry<T, E, F, Fut>(policy: &RetryPolicy, mut request: F) -> Result<T, E>
+where
+    E: Transient,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if e.is_transient() && attempt < policy.max_attempts => {
+                tokio::time::sleep(policy.delay(attempt, e.retry_after())).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -4,9 +4,11 @@ use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList, PyTuple, PyType};
 
+use crate::select::_Mapped;
+
 #[derive(Clone, Debug)]
 pub(crate) struct Schema {
-    pub mapping: HashMap<String, FieldType>,
+    pub mapping: HashMap<String, Column>,
 }
 impl Schema {
     pub(crate) fn from_py_schema(ob: Py<PyAny>) -> PyResult<Self> {
@@ -32,10 +34,25 @@ impl Schema {
             .map(|key| {
                 let value = props.get_item(key).unwrap();
                 let key: String = key.extract()?;
-                let value: FieldType = FieldType::extract_from_py_schema(value)?;
+                let value: Column = Column::extract_from_py_schema(&key, value)?;
                 Ok((key, value))
             })
-            .collect::<PyResult<HashMap<String, FieldType>>>()?;
+            .collect::<PyResult<HashMap<String, Column>>>()?;
+        for role in [ColumnRole::Measurement, ColumnRole::Timestamp] {
+            let mut keys = mapping
+                .iter()
+                .filter(|(_, column)| column.role == role)
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<_>>();
+            if keys.len() > 1 {
+                keys.sort_unstable();
+                return Err(PyValueError::new_err(format!(
+                    "A model can have a single {} column, got '{}'",
+                    role.value(),
+                    keys.join("', '")
+                )));
+            }
+        }
         Ok(Self { mapping })
     }
 
@@ -46,6 +63,84 @@ impl Schema {
         }
         Ok(props.into())
     }
+
+    pub(crate) fn measurement(&self) -> Option<(&String, &Column)> {
+        self.mapping
+            .iter()
+            .find(|(_, col)| col.role == ColumnRole::Measurement)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Column {
+    pub name: String,
+    pub role: ColumnRole,
+    pub field_type: FieldType,
+}
+
+impl Column {
+    fn extract_from_py_schema(key: &str, prop: &PyAny) -> PyResult<Self> {
+        let prop: &PyDict = prop.downcast()?;
+        let name = match prop.get_item("column") {
+            Some(name) => name.extract()?,
+            None => key.to_string(),
+        };
+        let role = match prop.get_item("role") {
+            Some(role) => ColumnRole::of(role.extract()?)?,
+            None => ColumnRole::Field,
+        };
+        let field_type = FieldType::extract_from_py_schema(prop)?;
+        Ok(Self {
+            name,
+            role,
+            field_type,
+        })
+    }
+
+    /// The name of the column in query results: the measurement and the
+    /// timestamp are always stored as `_measurement` and `_time`.
+    pub(crate) fn flux_name(&self) -> &str {
+        match self.role {
+            ColumnRole::Measurement => "_measurement",
+            ColumnRole::Timestamp => "_time",
+            ColumnRole::Tag | ColumnRole::Field => &self.name,
+        }
+    }
+
+    pub(crate) fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+        let dict = self.field_type.to_dict(py)?;
+        dict.as_ref(py).set_item("role", self.role.value())?;
+        Ok(dict)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColumnRole {
+    Measurement,
+    Tag,
+    Field,
+    Timestamp,
+}
+
+impl ColumnRole {
+    pub(crate) fn value(&self) -> &str {
+        match *self {
+            ColumnRole::Measurement => "measurement",
+            ColumnRole::Tag => "tag",
+            ColumnRole::Field => "field",
+            ColumnRole::Timestamp => "timestamp",
+        }
+    }
+
+    pub(crate) fn of(value: &str) -> PyResult<Self> {
+        match value {
+            "measurement" => Ok(ColumnRole::Measurement),
+            "tag" => Ok(ColumnRole::Tag),
+            "field" => Ok(ColumnRole::Field),
+            "timestamp" => Ok(ColumnRole::Timestamp),
+            _ => Err(PyValueError::new_err(format!("Invalid role: {}", value))),
+        }
+    }
 }
 
 #[pyfunction]
@@ -72,6 +167,11 @@ pub fn get_schema(cls: &PyType) -> PyResult<PyObject> {
             .try_for_each(|item| {
                 let (key, value) = item.extract::<(&str, &PyAny)>().unwrap();
                 let col_properties = PyDict::new(py);
+                if let Ok(mapped) = cls.getattr(key).and_then(|col| col.getattr("_mapped")) {
+                    let mapped: _Mapped = mapped.extract()?;
+                    col_properties.set_item("column", &mapped._col_name)?;
+                    col_properties.set_item("role", mapped._role.value())?;
+                }
                 match value.getattr("__args__") {
                     Ok(v) => {
                         let v: &PyTuple = v.downcast()?;
@@ -106,8 +206,10 @@ pub(crate) enum FieldType {
     Tuple { items: Vec<FieldType> },
     Str,
     Int,
+    UInt,
     Float,
     Bool,
+    DateTime,
     None,
 }
 
@@ -121,8 +223,10 @@ impl FieldType {
                 "null" => Ok(Self::None),
                 "bool" => Ok(Self::Bool),
                 "str" => Ok(Self::Str),
-                "number" => Ok(Self::Float),
+                "number" | "float" => Ok(Self::Float),
                 "int" => Ok(Self::Int),
+                "UInt" => Ok(Self::UInt),
+                "datetime" => Ok(Self::DateTime),
                 "object" => Ok(Self::Dict {
                     value: Box::new(Self::Str),
                 }),
@@ -153,6 +257,21 @@ impl FieldType {
         }
     }
 
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Self::Dict { .. } => "dict",
+            Self::List { .. } => "list",
+            Self::Tuple { .. } => "tuple",
+            Self::Str => "str",
+            Self::Int => "int",
+            Self::UInt => "UInt",
+            Self::Float => "float",
+            Self::Bool => "bool",
+            Self::DateTime => "datetime",
+            Self::None => "None",
+        }
+    }
+
     pub(crate) fn to_dict(&self, py: Python) -> PyResult<PyObject> {
         let dict = PyDict::new(py);
         match self {
@@ -174,8 +293,16 @@ impl FieldType {
                 dict.set_item("properties", value.to_dict(py)?)?;
             }
             Self::Int => dict.set_item("type", "integer")?,
+            Self::UInt => {
+                dict.set_item("type", "integer")?;
+                dict.set_item("minimum", 0)?;
+            }
             Self::Float => dict.set_item("type", "number")?,
             Self::Bool => dict.set_item("type", "boolean")?,
+            Self::DateTime => {
+                dict.set_item("type", "string")?;
+                dict.set_item("format", "date-time")?;
+            }
             Self::None => dict.set_item("type", "null")?,
         }
         Ok(dict.into())
@@ -1,141 +1,598 @@
-use pyo3::exceptions::PyValueError;
+use std::fmt;
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyType;
+use pyo3::types::{PyString, PyType};
+
+use crate::bucket::Decoding;
+use crate::duration::FluxDuration;
+use crate::query::{
+    Aggregate, AggregateWindow, BooleanExpr, ColumnRef, Comparison, Connective, FluxLiteral,
+    FluxQuery, MatchOperator, Membership, Pick, Predicate, Projection, Sort, StringMatch,
+    TimeBound, Transform,
+};
+use crate::schema::{get_schema, Column, ColumnRole, FieldType, Schema};
 
 #[pyclass(subclass)]
 pub struct _Select {
     _select: Py<PyType>,
-    _where_clauses: Vec<_WhereClause>,
-    _raw_query: String,
+    schema: Box<Schema>,
+    pub(crate) query: FluxQuery,
+    /// Schema keys of the `group_by()` columns, in the order of the group key.
+    group_by: Option<Vec<String>>,
+    /// Schema keys of the selected columns, `None` when selecting the model.
+    columns: Option<Vec<String>>,
+}
+
+impl _Select {
+    fn predicate(&self, py: Python, expr: &WhereExpr) -> PyResult<Predicate> {
+        let predicates = |operands: &[WhereExpr]| {
+            operands
+                .iter()
+                .map(|operand| self.predicate(py, operand))
+                .collect::<PyResult<Vec<_>>>()
+        };
+        Ok(match expr {
+            WhereExpr::Compare {
+                left_operand,
+                right_operand,
+                operator,
+            } => {
+                ColumnRef::validate(&left_operand._col_name)?;
+                let (key, column) = self.column(left_operand)?;
+                Predicate::Compare(Comparison {
+                    column: column.flux_name().to_string(),
+                    operator: operator.clone(),
+                    value: FluxLiteral::for_column(key, column, right_operand.as_ref(py))?,
+                })
+            }
+            WhereExpr::In {
+                left_operand,
+                values,
+            } => {
+                ColumnRef::validate(&left_operand._col_name)?;
+                let (key, column) = self.column(left_operand)?;
+                Predicate::In(Membership {
+                    column: column.flux_name().to_string(),
+                    values: values
+                        .iter()
+                        .map(|value| FluxLiteral::for_column(key, column, value.as_ref(py)))
+                        .collect::<PyResult<Vec<_>>>()?,
+                })
+            }
+            WhereExpr::Match {
+                left_operand,
+                operator,
+                pattern,
+            } => {
+                ColumnRef::validate(&left_operand._col_name)?;
+                let (key, column) = self.column(left_operand)?;
+                let is_string = match column.role {
+                    ColumnRole::Measurement | ColumnRole::Tag => true,
+                    ColumnRole::Field => matches!(column.field_type, FieldType::Str),
+                    ColumnRole::Timestamp => false,
+                };
+                if !is_string {
+                    return Err(PyTypeError::new_err(format!(
+                        "{}() needs a string column, '{}' is {}",
+                        operator.name(),
+                        key,
+                        column.field_type.name()
+                    )));
+                }
+                let string_match = StringMatch {
+                    column: column.flux_name().to_string(),
+                    operator: operator.clone(),
+                    pattern: pattern.clone(),
+                };
+                string_match.validate()?;
+                Predicate::Match(string_match)
+            }
+            WhereExpr::Exists { left_operand } => {
+                ColumnRef::validate(&left_operand._col_name)?;
+                let (_, column) = self.column(left_operand)?;
+                Predicate::Exists(column.flux_name().to_string())
+            }
+            WhereExpr::And(operands) => Predicate::And(predicates(operands)?),
+            WhereExpr::Or(operands) => Predicate::Or(predicates(operands)?),
+            WhereExpr::Not(operand) => Predicate::Not(Box::new(self.predicate(py, operand)?)),
+        })
+    }
+
+    /// How the bucket decodes the records of this select.
+    pub(crate) fn decoding(&self) -> Decoding {
+        Decoding {
+            aggregate: self.query.window.as_ref().map(|window| window.function),
+            transforms: self.query.transforms.clone(),
+            group_by: self.group_by.clone(),
+            single: self.query.pick.is_some(),
+            columns: self.columns.clone(),
+        }
+    }
+
+    fn column(&self, mapped: &_Mapped) -> PyResult<(&String, &Column)> {
+        self.schema
+            .mapping
+            .iter()
+            .find(|(_, column)| column.name == mapped._col_name && column.role == mapped._role)
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Column '{}' is not mapped by {}",
+                    mapped._col_name, self.query.bucket
+                ))
+            })
+    }
 }
 
 #[pymethods]
 impl _Select {
     #[new]
-    pub(crate) fn new(select: Py<PyType>) -> Self {
-        Self {
-            _select: select,
-            _where_clauses: Vec::new(),
-            _raw_query: String::new(),
+    #[args(columns = "None")]
+    pub(crate) fn new(select: &PyType, columns: Option<Vec<_Mapped>>) -> PyResult<Self> {
+        let schema = Schema::from_py_schema(get_schema(select)?)?;
+        let mut query = FluxQuery::new(select.getattr("__name__")?.extract()?);
+        query.fields = schema
+            .mapping
+            .values()
+            .filter(|column| column.role == ColumnRole::Field)
+            .map(|column| column.name.clone())
+            .collect();
+        query.fields.sort();
+        query.numeric_fields = schema
+            .mapping
+            .values()
+            .filter(|column| column.role == ColumnRole::Field)
+            .filter(|column| {
+                matches!(
+                    column.field_type,
+                    FieldType::Int | FieldType::UInt | FieldType::Float
+                )
+            })
+            .map(|column| column.name.clone())
+            .collect();
+        let mut this = Self {
+            _select: select.into(),
+            schema: Box::new(schema),
+            query,
+            group_by: None,
+            columns: None,
+        };
+        if let Some(columns) = columns {
+            if columns.is_empty() {
+                return Err(PyValueError::new_err("select() needs at least one column"));
+            }
+            let mut keys = Vec::new();
+            let mut projection = Projection::default();
+            for mapped in &columns {
+                let (key, column) = this.column(mapped)?;
+                match column.role {
+                    ColumnRole::Field => projection.fields.push(column.name.clone()),
+                    _ => projection.columns.push(column.flux_name().to_string()),
+                }
+                keys.push(key.clone());
+            }
+            this.query.projection = Some(projection);
+            this.columns = Some(keys);
+        }
+        Ok(this)
+    }
+
+    pub(crate) fn _where(&mut self, py: Python, clause: &_WhereClause) -> PyResult<()> {
+        let predicate = self.predicate(py, &clause.expr)?;
+        self.query.filters.push(predicate);
+        Ok(())
+    }
+
+    #[args(start = "None", stop = "None")]
+    pub(crate) fn _range(&mut self, start: Option<&PyAny>, stop: Option<&PyAny>) -> PyResult<()> {
+        let start = match start {
+            Some(start) => TimeBound::extract("start", start)?,
+            None => self.query.start.clone(),
+        };
+        let stop = match stop {
+            Some(stop) => Some(TimeBound::extract("stop", stop)?),
+            None => self.query.stop.clone(),
+        };
+        if let Some(stop) = &stop {
+            if !start.is_before(stop) {
+                return Err(PyValueError::new_err(format!(
+                    "Range start {} must be before stop {}",
+                    start, stop
+                )));
+            }
         }
+        self.query.start = start;
+        self.query.stop = stop;
+        Ok(())
     }
 
-    pub(crate) fn _where(
+    pub(crate) fn _aggregate_window(
         &mut self,
-        left_operand: _Mapped,
-        right_operand: Py<PyAny>,
-        operator: String,
-    ) {
-        self._where_clauses.push(_WhereClause {
-            _left_operand: left_operand,
-            _right_operand: right_operand,
-            _operator: _WhereOperator::of(&operator).unwrap(),
+        every: &PyAny,
+        function: &str,
+        create_empty: bool,
+    ) -> PyResult<()> {
+        self.query.window = Some(AggregateWindow::new(
+            FluxDuration::extract("every", every)?,
+            Aggregate::of(function)?,
+            create_empty,
+        )?);
+        Ok(())
+    }
+
+    pub(crate) fn _derivative(&mut self, unit: &PyAny, non_negative: bool) -> PyResult<()> {
+        let name = match non_negative {
+            true => "non_negative_derivative",
+            false => "derivative",
+        };
+        self.query.transforms.push(Transform::Derivative {
+            unit: Transform::unit(name, FluxDuration::extract("unit", unit)?)?,
+            non_negative,
         });
+        Ok(())
     }
 
-    pub(crate) fn _create_bucket_str(&mut self, name: String) {
-        self._raw_query = format!("from(bucket: \"{}\")", name);
+    pub(crate) fn _difference(&mut self, non_negative: bool) {
+        self.query
+            .transforms
+            .push(Transform::Difference { non_negative });
     }
 
-    pub(crate) fn _create_filter_str(&mut self) {
-        if !self._where_clauses.is_empty() {
-            for where_clause in self._where_clauses.iter() {
-                self._raw_query.push_str(" |> filter(fn: (r) => ");
-                let left_operand = &where_clause._left_operand._col_name;
-                let right_operand = where_clause._right_operand.to_string();
-                let operator: &str = where_clause._operator.value();
-                if let Ok(right_operand) = right_operand.parse::<i32>() {
-                    self._raw_query.push_str(&format!(
-                        "r.{} {} {})",
-                        left_operand, operator, right_operand
-                    ));
-                    continue;
-                }
+    pub(crate) fn _moving_average(&mut self, n: i64) -> PyResult<()> {
+        self.query.transforms.push(Transform::MovingAverage {
+            n: Transform::points("moving_average", n)?,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn _exponential_moving_average(&mut self, n: i64) -> PyResult<()> {
+        self.query
+            .transforms
+            .push(Transform::ExponentialMovingAverage {
+                n: Transform::points("exponential_moving_average", n)?,
+            });
+        Ok(())
+    }
+
+    pub(crate) fn _cumulative_sum(&mut self) {
+        self.query.transforms.push(Transform::CumulativeSum);
+    }
 
-                self._raw_query.push_str(&format!(
-                    "r.{} {} \"{}\")",
-                    left_operand, operator, right_operand
-                ));
+    pub(crate) fn _elapsed(&mut self, unit: &PyAny, column_name: String) -> PyResult<()> {
+        let (key, column) = self
+            .schema
+            .mapping
+            .iter()
+            .find(|(_, column)| column.name == column_name && column.role == ColumnRole::Field)
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "elapsed() needs a field column mapped by {}, got '{}'",
+                    self.query.bucket, column_name
+                ))
+            })?;
+        if !matches!(column.field_type, FieldType::Int) {
+            return Err(PyTypeError::new_err(format!(
+                "elapsed() needs an int column, '{}' is {}",
+                key,
+                column.field_type.name()
+            )));
+        }
+        let unit = Transform::unit("elapsed", FluxDuration::extract("unit", unit)?)?;
+        // the column is added by the query rather than stored as a field
+        self.query.fields.retain(|field| *field != column_name);
+        if let Some(projection) = &mut self.query.projection {
+            if let Some(i) = projection
+                .fields
+                .iter()
+                .position(|field| *field == column_name)
+            {
+                projection.columns.push(projection.fields.remove(i));
             }
         }
+        self.query.transforms.push(Transform::Elapsed {
+            unit,
+            column: column_name,
+        });
+        Ok(())
     }
 
-    pub(crate) fn _create_range_str(&mut self) {
-        self._raw_query.push_str(" |> range(start: -1h)");
+    pub(crate) fn _group_by(&mut self, columns: Vec<_Mapped>) -> PyResult<()> {
+        if columns.is_empty() {
+            return Err(PyValueError::new_err(
+                "group_by() needs at least one column",
+            ));
+        }
+        let mut keys = Vec::new();
+        let mut group = Vec::new();
+        for mapped in &columns {
+            let (key, column) = self.column(mapped)?;
+            if matches!(column.role, ColumnRole::Field | ColumnRole::Timestamp) {
+                return Err(PyTypeError::new_err(format!(
+                    "group_by() needs a tag or measurement column, '{}' is a {}",
+                    key,
+                    column.role.value()
+                )));
+            }
+            group.push(column.flux_name().to_string());
+            keys.push(key.clone());
+        }
+        self.query.group = Some(group);
+        self.group_by = Some(keys);
+        Ok(())
     }
 
-    pub(crate) fn _create_raw_query(&mut self) {
-        self._create_range_str();
-        self._create_filter_str();
+    pub(crate) fn _order_by(&mut self, orderings: Vec<_Ordering>) -> PyResult<()> {
+        let desc = match orderings.first() {
+            Some(ordering) => ordering.desc,
+            None => {
+                return Err(PyValueError::new_err(
+                    "order_by() needs at least one column",
+                ))
+            }
+        };
+        if orderings.iter().any(|ordering| ordering.desc != desc) {
+            return Err(PyValueError::new_err(
+                "order_by() columns must all be ascending or all descending",
+            ));
+        }
+        let columns = orderings
+            .iter()
+            .map(|ordering| Ok(self.column(&ordering.column)?.1.flux_name().to_string()))
+            .collect::<PyResult<Vec<_>>>()?;
+        self.query.sort = Some(Sort { columns, desc });
+        Ok(())
+    }
+
+    pub(crate) fn _limit(&mut self, n: i64) -> PyResult<()> {
+        self.query.limit = Some(non_negative("limit", n)?);
+        Ok(())
+    }
+
+    pub(crate) fn _offset(&mut self, n: i64) -> PyResult<()> {
+        self.query.offset = non_negative("offset", n)?;
+        Ok(())
     }
 
-    pub(crate) fn _get_raw_query(&self) -> PyResult<String> {
-        Ok(self._raw_query.to_string())
+    /// Limits the select to its first record, in time order unless sorted.
+    pub(crate) fn _first(&mut self) {
+        self.query.limit = Some(1);
+        self.query.pick = Some(Pick::First);
+    }
+
+    /// Limits the select to its last record, in time order unless sorted.
+    pub(crate) fn _last(&mut self) {
+        self.query.limit = Some(1);
+        self.query.pick = Some(Pick::Last);
+    }
+
+    pub(crate) fn to_flux(&self) -> PyResult<String> {
+        self.query.to_flux()
     }
 }
 
-#[pyclass(subclass)]
-pub struct _WhereClause {
-    _left_operand: _Mapped,
-    _right_operand: Py<PyAny>,
-    _operator: _WhereOperator,
+fn non_negative(method: &str, n: i64) -> PyResult<u64> {
+    u64::try_from(n)
+        .map_err(|_| PyValueError::new_err(format!("{}() can't be negative, got {}", method, n)))
+}
+
+/// The sort direction of a column, built by `asc()` and `desc()`.
+#[derive(Clone)]
+#[pyclass]
+pub struct _Ordering {
+    column: _Mapped,
+    desc: bool,
 }
 
 #[pymethods]
-impl _WhereClause {
-    #[new]
-    pub(crate) fn new(
+impl _Ordering {
+    pub(crate) fn __str__(&self) -> PyResult<String> {
+        Ok(format!(
+            "{} {}",
+            self.column._col_name,
+            if self.desc { "DESC" } else { "ASC" }
+        ))
+    }
+}
+
+/// A where clause: a comparison between a column and a value, membership
+/// of a column in a set of values, a string match, the existence of a
+/// column, or `and`, `or` and `not` of other clauses.
+#[derive(Clone)]
+pub(crate) enum WhereExpr {
+    Compare {
         left_operand: _Mapped,
         right_operand: Py<PyAny>,
         operator: _WhereOperator,
-    ) -> Self {
-        Self {
-            _left_operand: left_operand,
-            _right_operand: right_operand,
-            _operator: operator,
+    },
+    In {
+        left_operand: _Mapped,
+        values: Vec<Py<PyAny>>,
+    },
+    Match {
+        left_operand: _Mapped,
+        operator: MatchOperator,
+        pattern: String,
+    },
+    Exists {
+        left_operand: _Mapped,
+    },
+    And(Vec<WhereExpr>),
+    Or(Vec<WhereExpr>),
+    Not(Box<WhereExpr>),
+}
+
+impl BooleanExpr for WhereExpr {
+    fn connective(&self) -> Option<Connective> {
+        match self {
+            WhereExpr::Or(_) => Some(Connective::Or),
+            WhereExpr::And(_) => Some(Connective::And),
+            WhereExpr::Not(_)
+            | WhereExpr::Compare { .. }
+            | WhereExpr::In { .. }
+            | WhereExpr::Match { .. }
+            | WhereExpr::Exists { .. } => None,
         }
     }
+}
 
-    pub(crate) fn __str__(&self) -> PyResult<String> {
-        let left_operand = &self._left_operand._col_name;
-        let right_operand = self._right_operand.to_string();
-        let operator = match self._operator {
-            _WhereOperator::EQ => "==",
-            _WhereOperator::NE => "!=",
-            _WhereOperator::GT => ">",
-            _WhereOperator::GE => ">=",
-            _WhereOperator::LT => "<",
-            _WhereOperator::LE => "<=",
-        };
-        Ok(format!("{} {} {}", left_operand, operator, right_operand))
+impl fmt::Display for WhereExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhereExpr::Compare {
+                left_operand,
+                right_operand,
+                operator,
+            } => write!(
+                f,
+                "{} {} {}",
+                left_operand._col_name,
+                operator.value(),
+                right_operand
+            ),
+            WhereExpr::In {
+                left_operand,
+                values,
+            } => write!(
+                f,
+                "{} in [{}]",
+                left_operand._col_name,
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            WhereExpr::Match {
+                left_operand,
+                operator,
+                pattern,
+            } => write!(
+                f,
+                "{}.{}({})",
+                left_operand._col_name,
+                operator.name(),
+                FluxLiteral::String(pattern.clone())
+            ),
+            WhereExpr::Exists { left_operand } => {
+                write!(f, "{} is not None", left_operand._col_name)
+            }
+            WhereExpr::And(operands) | WhereExpr::Or(operands) => self.join(f, operands),
+            WhereExpr::Not(operand) => match &**operand {
+                WhereExpr::Exists { left_operand } => {
+                    write!(f, "{} is None", left_operand._col_name)
+                }
+                operand => write!(f, "not ({})", operand),
+            },
+        }
     }
+}
 
-    pub(crate) fn get_left_operand(&self) -> PyResult<_Mapped> {
-        Ok(self._left_operand.clone())
-    }
+#[pyclass(subclass)]
+pub struct _WhereClause {
+    pub(crate) expr: WhereExpr,
+}
 
-    pub(crate) fn get_right_operand(&self) -> PyResult<String> {
-        Ok(self._right_operand.to_string())
+impl _WhereClause {
+    fn compare(left_operand: &_Mapped, right_operand: Py<PyAny>, operator: _WhereOperator) -> Self {
+        Self {
+            expr: WhereExpr::Compare {
+                left_operand: left_operand.clone(),
+                right_operand,
+                operator,
+            },
+        }
     }
 
-    pub(crate) fn get_operator_str(&self) -> PyResult<String> {
-        let operator = match self._operator {
-            _WhereOperator::EQ => "==",
-            _WhereOperator::NE => "!=",
-            _WhereOperator::GT => ">",
-            _WhereOperator::GE => ">=",
-            _WhereOperator::LT => "<",
-            _WhereOperator::LE => "<=",
-        };
-        Ok(operator.to_string())
+    fn leaf(&self) -> PyResult<(&_Mapped, &Py<PyAny>, &_WhereOperator)> {
+        match &self.expr {
+            WhereExpr::Compare {
+                left_operand,
+                right_operand,
+                operator,
+            } => Ok((left_operand, right_operand, operator)),
+            _ => Err(PyValueError::new_err(format!(
+                "'{}' is not a single comparison",
+                self.expr
+            ))),
+        }
     }
+}
+
+// pyo3 0.17 expands binary operators such as `__and__` into impls outside of
+// the `#[pymethods]` block, so the lint is allowed on a module around it
+#[allow(non_local_definitions)]
+mod where_clause_methods {
+    use super::*;
+
+    #[pymethods]
+    impl _WhereClause {
+        #[new]
+        pub(crate) fn new(
+            left_operand: _Mapped,
+            right_operand: Py<PyAny>,
+            operator: _WhereOperator,
+        ) -> Self {
+            Self::compare(&left_operand, right_operand, operator)
+        }
+
+        pub(crate) fn __str__(&self) -> PyResult<String> {
+            Ok(self.expr.to_string())
+        }
 
-    pub(crate) fn get_operator(&self) -> PyResult<_WhereOperator> {
-        Ok(self._operator.clone())
+        pub(crate) fn __and__(&self, other: &_WhereClause) -> _WhereClause {
+            let mut operands = Vec::new();
+            for expr in [&self.expr, &other.expr] {
+                match expr {
+                    WhereExpr::And(inner) => operands.extend(inner.iter().cloned()),
+                    expr => operands.push(expr.clone()),
+                }
+            }
+            _WhereClause {
+                expr: WhereExpr::And(operands),
+            }
+        }
+
+        pub(crate) fn __or__(&self, other: &_WhereClause) -> _WhereClause {
+            let mut operands = Vec::new();
+            for expr in [&self.expr, &other.expr] {
+                match expr {
+                    WhereExpr::Or(inner) => operands.extend(inner.iter().cloned()),
+                    expr => operands.push(expr.clone()),
+                }
+            }
+            _WhereClause {
+                expr: WhereExpr::Or(operands),
+            }
+        }
+
+        pub(crate) fn __invert__(&self) -> _WhereClause {
+            _WhereClause {
+                expr: match &self.expr {
+                    WhereExpr::Not(inner) => (**inner).clone(),
+                    expr => WhereExpr::Not(Box::new(expr.clone())),
+                },
+            }
+        }
+
+        pub(crate) fn get_left_operand(&self) -> PyResult<_Mapped> {
+            Ok(self.leaf()?.0.clone())
+        }
+
+        pub(crate) fn get_right_operand(&self, py: Python) -> PyResult<Py<PyAny>> {
+            Ok(self.leaf()?.1.clone_ref(py))
+        }
+
+        pub(crate) fn get_operator_str(&self) -> PyResult<String> {
+            Ok(self.leaf()?.2.value().to_string())
+        }
+
+        pub(crate) fn get_operator(&self) -> PyResult<_WhereOperator> {
+            Ok(self.leaf()?.2.clone())
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 #[pyclass]
 pub enum _WhereOperator {
     EQ,
@@ -147,7 +604,7 @@ pub enum _WhereOperator {
 }
 
 impl _WhereOperator {
-    fn value(&self) -> &str {
+    pub(crate) fn value(&self) -> &str {
         match *self {
             _WhereOperator::EQ => "==",
             _WhereOperator::NE => "!=",
@@ -157,87 +614,156 @@ impl _WhereOperator {
             _WhereOperator::LE => "<=",
         }
     }
-
-    fn of(value: &str) -> PyResult<Self> {
-        match value {
-            "==" => Ok(_WhereOperator::EQ),
-            "!=" => Ok(_WhereOperator::NE),
-            ">" => Ok(_WhereOperator::GT),
-            ">=" => Ok(_WhereOperator::GE),
-            "<" => Ok(_WhereOperator::LT),
-            "<=" => Ok(_WhereOperator::LE),
-            _ => Err(PyValueError::new_err(format!(
-                "Invalid operator: {}",
-                value
-            ))),
-        }
-    }
 }
 
 #[derive(Clone)]
 #[pyclass(subclass)]
 pub struct _Mapped {
     pub _col_name: String,
+    pub(crate) _role: ColumnRole,
+}
+
+impl _Mapped {
+    fn exists(&self) -> _WhereClause {
+        _WhereClause {
+            expr: WhereExpr::Exists {
+                left_operand: self.clone(),
+            },
+        }
+    }
+
+    fn none_only(&self, method: &str, value: &PyAny) -> PyResult<&Self> {
+        if !value.is_none() {
+            return Err(PyTypeError::new_err(format!(
+                "{}() on '{}' only accepts None, compare values with == and !=",
+                method, self._col_name
+            )));
+        }
+        Ok(self)
+    }
+
+    fn string_match(&self, operator: MatchOperator, pattern: String) -> _WhereClause {
+        _WhereClause {
+            expr: WhereExpr::Match {
+                left_operand: self.clone(),
+                operator,
+                pattern,
+            },
+        }
+    }
 }
 
 #[pymethods]
 impl _Mapped {
     #[new]
-    pub(crate) fn new(col_name: String) -> Self {
-        Self {
+    #[args(role = "\"field\"")]
+    pub(crate) fn new(col_name: String, role: &str) -> PyResult<Self> {
+        Ok(Self {
             _col_name: col_name,
-        }
+            _role: ColumnRole::of(role)?,
+        })
     }
 
-    pub(crate) fn __eq__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::EQ,
+    pub(crate) fn __eq__(&mut self, py: Python, value: Py<PyAny>) -> _WhereClause {
+        match value.is_none(py) {
+            true => self.exists().__invert__(),
+            false => _WhereClause::compare(self, value, _WhereOperator::EQ),
         }
     }
 
-    pub(crate) fn __ne__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::NE,
+    pub(crate) fn __ne__(&mut self, py: Python, value: Py<PyAny>) -> _WhereClause {
+        match value.is_none(py) {
+            true => self.exists(),
+            false => _WhereClause::compare(self, value, _WhereOperator::NE),
         }
     }
 
     pub(crate) fn __gt__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::GT,
-        }
+        _WhereClause::compare(self, value, _WhereOperator::GT)
     }
 
     pub(crate) fn __ge__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::GE,
-        }
+        _WhereClause::compare(self, value, _WhereOperator::GE)
     }
 
     pub(crate) fn __lt__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::LT,
-        }
+        _WhereClause::compare(self, value, _WhereOperator::LT)
     }
 
     pub(crate) fn __le__(&mut self, value: Py<PyAny>) -> _WhereClause {
-        _WhereClause {
-            _left_operand: self.clone(),
-            _right_operand: value,
-            _operator: _WhereOperator::LE,
+        _WhereClause::compare(self, value, _WhereOperator::LE)
+    }
+
+    pub(crate) fn in_(&self, values: &PyAny) -> PyResult<_WhereClause> {
+        if values.is_instance_of::<PyString>()? {
+            return Err(PyTypeError::new_err(format!(
+                "in_() on '{}' expects a collection of values, got str",
+                self._col_name
+            )));
+        }
+        let values = values
+            .iter()?
+            .map(|value| value.map(Into::into))
+            .collect::<PyResult<Vec<Py<PyAny>>>>()?;
+        Ok(_WhereClause {
+            expr: WhereExpr::In {
+                left_operand: self.clone(),
+                values,
+            },
+        })
+    }
+
+    pub(crate) fn not_in(&self, values: &PyAny) -> PyResult<_WhereClause> {
+        Ok(self.in_(values)?.__invert__())
+    }
+
+    pub(crate) fn matches(&self, pattern: String) -> _WhereClause {
+        self.string_match(MatchOperator::Matches, pattern)
+    }
+
+    pub(crate) fn not_matches(&self, pattern: String) -> _WhereClause {
+        self.string_match(MatchOperator::NotMatches, pattern)
+    }
+
+    pub(crate) fn startswith(&self, prefix: String) -> _WhereClause {
+        self.string_match(MatchOperator::StartsWith, prefix)
+    }
+
+    pub(crate) fn endswith(&self, suffix: String) -> _WhereClause {
+        self.string_match(MatchOperator::EndsWith, suffix)
+    }
+
+    pub(crate) fn contains(&self, substring: String) -> _WhereClause {
+        self.string_match(MatchOperator::Contains, substring)
+    }
+
+    pub(crate) fn is_(&self, value: &PyAny) -> PyResult<_WhereClause> {
+        Ok(self.none_only("is_", value)?.exists().__invert__())
+    }
+
+    pub(crate) fn is_not(&self, value: &PyAny) -> PyResult<_WhereClause> {
+        Ok(self.none_only("is_not", value)?.exists())
+    }
+
+    pub(crate) fn asc(&self) -> _Ordering {
+        _Ordering {
+            column: self.clone(),
+            desc: false,
+        }
+    }
+
+    pub(crate) fn desc(&self) -> _Ordering {
+        _Ordering {
+            column: self.clone(),
+            desc: true,
         }
     }
 
     pub(crate) fn _get_col_name(&self) -> PyResult<String> {
         Ok(self._col_name.clone())
     }
+
+    pub(crate) fn _get_role(&self) -> PyResult<String> {
+        Ok(self._role.value().to_string())
+    }
 }
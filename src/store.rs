@@ -1,18 +1,17 @@
 use std::collections::HashMap;
 
-use influxdb2::models::{PostBucketRequest, Status};
-use influxdb2::Client;
 use pyo3::exceptions::{PyConnectionError, PyKeyError};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList, PyType};
 
-use crate::bucket::{BucketMeta, _Bucket};
+use crate::bucket::{_Bucket, BucketMeta};
+use crate::connection::Connection;
 use crate::engine::PyEngine;
 use crate::schema::Schema;
 
 #[pyclass(subclass)]
 pub struct _Store {
-    client: Client,
+    connection: Connection,
     registry: _Registry,
 }
 
@@ -20,26 +19,23 @@ pub struct _Store {
 impl _Store {
     #[new]
     pub fn new(bind: PyEngine, registry: _Registry) -> PyResult<Self> {
-        let client = Client::new(&bind.host, &bind.org_id, &bind.token);
-        Ok(_Store { client, registry })
+        let connection = Connection::new(&bind)?;
+        Ok(_Store {
+            connection,
+            registry,
+        })
     }
 
     pub(crate) fn healthy<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
-        let client = self.client.clone();
+        let connection = self.connection.clone();
         pyo3_asyncio::tokio::future_into_py_with_locals(
             py,
             pyo3_asyncio::tokio::get_current_locals(py)?,
             async move {
-                let status = client
-                    .health()
+                let healthy = connection
+                    .healthy()
                     .await
-                    .map_err(|e| PyConnectionError::new_err(e.to_string()))?
-                    .status;
-
-                let healthy = match status {
-                    Status::Pass => true,
-                    Status::Fail => false,
-                };
+                    .map_err(|e| PyConnectionError::new_err(e.to_string()))?;
                 Python::with_gil(|py| Ok(healthy.into_py(py)))
             },
         )
@@ -49,7 +45,11 @@ impl _Store {
         let model_name: String = Python::with_gil(|py| model.getattr(py, "__name__")?.extract(py))?;
 
         if let Some(meta) = self.registry.buckets_meta.get(&model_name) {
-            Ok(_Bucket::new(model_name, meta.clone(), self.client.clone()))
+            Ok(_Bucket::new(
+                model_name,
+                meta.clone(),
+                self.connection.clone(),
+            ))
         } else {
             Err(PyKeyError::new_err(format!(
                 "{} does not exist in the registry",
@@ -63,7 +63,7 @@ impl _Store {
             .registry
             .buckets_meta
             .iter()
-            .map(|(name, meta)| _Bucket::new(name.clone(), meta.clone(), self.client.clone()))
+            .map(|(name, meta)| _Bucket::new(name.clone(), meta.clone(), self.connection.clone()))
             .collect())
     }
 
@@ -82,16 +82,15 @@ impl _Store {
             .model_type_map
             .insert(model_name.clone(), model);
 
-        let client = self.client.clone();
-        let bucket_options = Some(PostBucketRequest::new(self.client.org.clone(), model_name));
+        let connection = self.connection.clone();
         pyo3_asyncio::tokio::future_into_py_with_locals(
             py,
             pyo3_asyncio::tokio::get_current_locals(py)?,
             async move {
-                client
-                    .create_bucket(bucket_options)
+                connection
+                    .create_bucket(&model_name)
                     .await
-                    .map_err(|e| pyo3::exceptions::PyConnectionError::new_err(e.to_string()))?;
+                    .map_err(|e| PyConnectionError::new_err(e.to_string()))?;
                 Python::with_gil(|py| Ok(py.None()))
             },
         )
@@ -106,25 +105,15 @@ impl _Store {
         self.registry.buckets_meta.remove(&model_name);
         self.registry.model_type_map.remove(&model_name);
 
-        let client = self.client.clone();
+        let connection = self.connection.clone();
         pyo3_asyncio::tokio::future_into_py_with_locals(
             py,
             pyo3_asyncio::tokio::get_current_locals(py)?,
             async move {
-                let buckets = client
-                    .list_buckets(None)
+                connection
+                    .delete_bucket(&model_name)
                     .await
-                    .map_err(|e| pyo3::exceptions::PyConnectionError::new_err(e.to_string()))?;
-
-                let bucket = buckets.buckets.into_iter().find(|b| b.name == model_name);
-
-                if let Some(bucket) = bucket {
-                    if let Some(bucket_id) = bucket.id {
-                        client.delete_bucket(&bucket_id).await.map_err(|e| {
-                            pyo3::exceptions::PyConnectionError::new_err(e.to_string())
-                        })?;
-                    }
-                };
+                    .map_err(|e| PyConnectionError::new_err(e.to_string()))?;
                 Python::with_gil(|py| Ok(py.None()))
             },
         )
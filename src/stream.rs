@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyConnectionError, PyStopAsyncIteration};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use tokio::sync::Mutex;
+
+use crate::bucket::{flux_error, Decoding};
+use crate::connection::{Connection, HttpError};
+use crate::flux::{merge_fields, FluxRecord, StreamParser};
+use crate::schema::Schema;
+
+/// An async iterator over the records of a query, yielding a list of dicts
+/// for each chunk of the response. The query is sent on the first iteration.
+#[pyclass]
+pub(crate) struct _QueryStream {
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl _QueryStream {
+    pub(crate) fn new(
+        connection: Connection,
+        query: String,
+        schema: Box<Schema>,
+        decoding: Decoding,
+    ) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(StreamState {
+                connection,
+                query: Some(query),
+                response: None,
+                parser: StreamParser::default(),
+                schema,
+                decoding,
+            })),
+        }
+    }
+}
+
+#[pymethods]
+impl _QueryStream {
+    pub(crate) fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub(crate) fn __anext__<'a>(&self, py: Python<'a>) -> PyResult<Option<&'a PyAny>> {
+        let state = self.state.clone();
+        let batch = pyo3_asyncio::tokio::future_into_py_with_locals(
+            py,
+            pyo3_asyncio::tokio::get_current_locals(py)?,
+            async move {
+                let mut state = state.lock().await;
+                let records = match state.next().await? {
+                    Some(records) => records,
+                    None => return Err(PyStopAsyncIteration::new_err(())),
+                };
+                Python::with_gil(|py| {
+                    let batch = PyList::empty(py);
+                    for record in merge_fields(records) {
+                        let (data, _) = state.decoding.decode(
+                            py,
+                            &state.schema,
+                            state.connection.precision,
+                            &record,
+                        )?;
+                        batch.append(data)?;
+                    }
+                    Ok(Into::<PyObject>::into(batch))
+                })
+            },
+        )?;
+        Ok(Some(batch))
+    }
+
+    /// Closes the response, the next iteration stops.
+    pub(crate) fn close<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        let state = self.state.clone();
+        pyo3_asyncio::tokio::future_into_py_with_locals(
+            py,
+            pyo3_asyncio::tokio::get_current_locals(py)?,
+            async move {
+                let mut state = state.lock().await;
+                state.query = None;
+                state.response = None;
+                Python::with_gil(|py| Ok(py.None()))
+            },
+        )
+    }
+}
+
+struct StreamState {
+    connection: Connection,
+    /// The query, until it is sent.
+    query: Option<String>,
+    /// The response being read, dropped to close it.
+    response: Option<reqwest::Response>,
+    parser: StreamParser,
+    schema: Box<Schema>,
+    decoding: Decoding,
+}
+
+impl StreamState {
+    /// Reads the response until some records are complete, `None` once it
+    /// has ended or was closed.
+    async fn next(&mut self) -> PyResult<Option<Vec<FluxRecord>>> {
+        loop {
+            // the response belongs to this future while a chunk is read, so
+            // that cancelling the read or failing drops it
+            let mut response = match (self.response.take(), self.query.take()) {
+                (Some(response), _) => response,
+                (None, Some(query)) => self
+                    .connection
+                    .query_stream(&query)
+                    .await
+                    .map_err(HttpError::query_error)?,
+                (None, None) => return Ok(None),
+            };
+            let chunk = response
+                .chunk()
+                .await
+                .map_err(|e| PyConnectionError::new_err(HttpError::from(e).to_string()))?;
+            let records = match chunk {
+                Some(chunk) => {
+                    let records = self.parser.feed(&chunk).map_err(flux_error)?;
+                    self.response = Some(response);
+                    records
+                }
+                None => self.parser.finish().map_err(flux_error)?,
+            };
+            if !records.is_empty() {
+                return Ok(Some(records));
+            }
+            if self.response.is_none() {
+                return Ok(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use influxdb2::models::WriteDataPoint;
+use pyo3::exceptions::{PyConnectionError, PyRuntimeError};
+use pyo3::prelude::*;
+
+use crate::bucket::transform_point;
+use crate::connection::Connection;
+use crate::point::{chunk_points, Point};
+use crate::schema::Schema;
+
+#[pyclass(subclass)]
+pub(crate) struct _BufferedWriter {
+    name: String,
+    schema: Box<Schema>,
+    state: Arc<WriterState>,
+}
+
+impl _BufferedWriter {
+    pub(crate) fn new(
+        name: String,
+        schema: Box<Schema>,
+        connection: Connection,
+        options: WriterOptions,
+    ) -> Self {
+        let flush_interval = options.flush_interval;
+        let state = Arc::new(WriterState {
+            buffer: Mutex::new(Vec::new()),
+            flushing: tokio::sync::Mutex::new(()),
+            notify: tokio::sync::Notify::new(),
+            closed: AtomicBool::new(false),
+            error: Mutex::new(None),
+            connection,
+            name: name.clone(),
+            options,
+        });
+
+        let background = state.clone();
+        pyo3_asyncio::tokio::get_runtime().spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(flush_interval) => {},
+                    _ = background.notify.notified() => {},
+                }
+                let closed = background.closed.load(Ordering::SeqCst);
+                background.flush().await;
+                if closed {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            name,
+            schema,
+            state,
+        }
+    }
+}
+
+#[pymethods]
+impl _BufferedWriter {
+    pub(crate) fn add(&self, item: Py<PyAny>) -> PyResult<()> {
+        if self.state.closed.load(Ordering::SeqCst) {
+            return Err(PyRuntimeError::new_err("Writer is closed"));
+        }
+        let precision = self.state.connection.precision;
+        let point = transform_point(&self.name, &self.schema, precision, &item)?;
+        let buffered = {
+            let mut buffer = self.state.buffer.lock().unwrap();
+            buffer.push(point);
+            buffer.len()
+        };
+        if buffered >= self.state.options.max_points {
+            self.state.notify.notify_one();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn pending(&self) -> PyResult<usize> {
+        Ok(self.state.buffer.lock().unwrap().len())
+    }
+
+    pub(crate) fn flush<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        let state = self.state.clone();
+        pyo3_asyncio::tokio::future_into_py_with_locals(
+            py,
+            pyo3_asyncio::tokio::get_current_locals(py)?,
+            async move {
+                let written = state.flush().await;
+                state.take_error()?;
+                Python::with_gil(|py| Ok(written.into_py(py)))
+            },
+        )
+    }
+
+    pub(crate) fn close<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        self.state.closed.store(true, Ordering::SeqCst);
+        self.state.notify.notify_one();
+        self.flush(py)
+    }
+}
+
+impl Drop for _BufferedWriter {
+    fn drop(&mut self) {
+        // let the background task write what is left and stop
+        self.state.closed.store(true, Ordering::SeqCst);
+        self.state.notify.notify_one();
+    }
+}
+
+pub(crate) struct WriterOptions {
+    pub(crate) max_points: usize,
+    pub(crate) max_bytes: usize,
+    pub(crate) flush_interval: Duration,
+    pub(crate) on_error: Option<Py<PyAny>>,
+}
+
+struct WriterState {
+    buffer: Mutex<Vec<Point>>,
+    flushing: tokio::sync::Mutex<()>,
+    notify: tokio::sync::Notify,
+    closed: AtomicBool,
+    error: Mutex<Option<(String, usize)>>,
+    connection: Connection,
+    name: String,
+    options: WriterOptions,
+}
+
+impl WriterState {
+    /// Writes every buffered point and returns how many were written.
+    /// Failed batches go to the `on_error` callback, or are kept as an error
+    /// raised by the next `flush()` when there is no callback.
+    async fn flush(&self) -> usize {
+        let _flushing = self.flushing.lock().await;
+        let points = std::mem::take(&mut *self.buffer.lock().unwrap());
+        let mut written = 0;
+        for batch in chunk_points(points, self.options.max_points, self.options.max_bytes) {
+            let len = batch.len();
+            match self.connection.write(&self.name, &batch).await {
+                Ok(_) => written += len,
+                Err(e) => self.report(e.to_string(), batch),
+            }
+        }
+        written
+    }
+
+    fn report(&self, error: String, batch: Vec<Point>) {
+        match &self.options.on_error {
+            Some(on_error) => Python::with_gil(|py| {
+                let lines = batch
+                    .iter()
+                    .map(|point| {
+                        let mut line = Vec::new();
+                        point.write_data_point_to(&mut line)?;
+                        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+                    })
+                    .collect::<std::io::Result<Vec<String>>>()
+                    .unwrap_or_default();
+                let exception = PyConnectionError::new_err(error);
+                if let Err(e) = on_error.call1(py, (exception.value(py), lines)) {
+                    e.print(py);
+                }
+            }),
+            None => {
+                let mut last = self.error.lock().unwrap();
+                let dropped = last.as_ref().map_or(0, |(_, dropped)| *dropped);
+                *last = Some((error, dropped + batch.len()));
+            }
+        }
+    }
+
+    fn take_error(&self) -> PyResult<()> {
+        match self.error.lock().unwrap().take() {
+            Some((error, dropped)) => Err(PyConnectionError::new_err(format!(
+                "{} ({} points dropped)",
+                error, dropped
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -5,7 +5,7 @@ import pytest
 import pytest_asyncio
 from dotenv import load_dotenv
 
-from aluminum import Base, Store, create_engine
+from aluminum import Base, Role, Store, create_engine
 from aluminum.mapped_column import Mapped, mapped_column
 
 logger = logging.getLogger(__name__)
@@ -16,9 +16,9 @@ org_id = os.getenv("ORG_ID") or ""
 
 
 class MockBucket(Base):
-    measurement: Mapped[int] = mapped_column("measurement")
-    tag: Mapped[str] = mapped_column("tag")
-    field: Mapped[int] = mapped_column("field")
+    measurement: Mapped[int] = mapped_column("measurement", role=Role.MEASUREMENT)
+    tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+    field: Mapped[int] = mapped_column("field", role=Role.FIELD)
 
 
 @pytest.fixture(scope="function", autouse=True)
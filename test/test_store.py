@@ -1,11 +1,13 @@
 from test.conftest import MockBucket, delete_mock_bucket, token
 
+from datetime import datetime, timedelta, timezone
+
 import pytest
 
-from aluminum import Store, create_engine
+from aluminum import RetryPolicy, Role, Store, UInt, create_engine
 from aluminum.base import Base
 from aluminum.mapped_column import Mapped, mapped_column
-from aluminum.select import select
+from aluminum.select import and_, not_, or_, select
 
 
 @pytest.mark.asyncio
@@ -26,12 +28,37 @@ async def test_bad_engine():
         await store.healthy()
 
 
+@pytest.mark.asyncio
+async def test_bad_engine_with_retry():
+    bad_engine = create_engine(
+        host="http://localhost:1337",
+        token=token,
+        org_id="7e1e96f08517702b",
+        retry=RetryPolicy(max_attempts=3, initial_backoff=0.01, jitter=False),
+    )
+    store = Store(bind=bad_engine)
+    with pytest.raises(ConnectionError):
+        await store.healthy()
+
+
+def test_bad_retry_policy():
+    for retry in [RetryPolicy(max_attempts=0), RetryPolicy(max_backoff=1e20)]:
+        engine = create_engine(
+            host="http://localhost:8086",
+            token=token,
+            org_id="",
+            retry=retry,
+        )
+        with pytest.raises(ValueError):
+            Store(bind=engine)
+
+
 @pytest.mark.asyncio
 async def test_get_buckets(store: Store):
     class MockBucket2(Base):
-        measurement: Mapped[int] = mapped_column("measurement")
-        tag: Mapped[str] = mapped_column("tag")
-        field: Mapped[int] = mapped_column("field")
+        measurement: Mapped[int] = mapped_column("measurement", role=Role.MEASUREMENT)
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
 
     await store.create_bucket(MockBucket)
     await store.create_bucket(MockBucket2)
@@ -45,9 +72,9 @@ async def test_get_buckets(store: Store):
             "name": "MockBucket",
             "meta": {
                 "schema": {
-                    "field": {"type": "integer"},
-                    "measurement": {"type": "integer"},
-                    "tag": {"type": "string"},
+                    "field": {"type": "integer", "role": "field"},
+                    "measurement": {"type": "integer", "role": "measurement"},
+                    "tag": {"type": "string", "role": "tag"},
                 }
             },
         },
@@ -55,9 +82,9 @@ async def test_get_buckets(store: Store):
             "name": "MockBucket2",
             "meta": {
                 "schema": {
-                    "field": {"type": "integer"},
-                    "measurement": {"type": "integer"},
-                    "tag": {"type": "string"},
+                    "field": {"type": "integer", "role": "field"},
+                    "measurement": {"type": "integer", "role": "measurement"},
+                    "tag": {"type": "string", "role": "tag"},
                 }
             },
         },
@@ -74,9 +101,9 @@ async def test_create_bucket(store: Store):
         "name": "MockBucket",
         "meta": {
             "schema": {
-                "field": {"type": "integer"},
-                "measurement": {"type": "integer"},
-                "tag": {"type": "string"},
+                "field": {"type": "integer", "role": "field"},
+                "measurement": {"type": "integer", "role": "measurement"},
+                "tag": {"type": "string", "role": "tag"},
             }
         },
     }
@@ -96,12 +123,166 @@ async def test_add_measurement(store: Store):
     await bucket.add(measurement)
 
 
+@pytest.mark.asyncio
+async def test_add_measurement_with_roles(store: Store):
+    class SensorBucket(Base):
+        name: Mapped[str] = mapped_column("_measurement", role=Role.MEASUREMENT)
+        sensor_id: Mapped[str] = mapped_column("sensor_id", role=Role.TAG)
+        temperature: Mapped[int] = mapped_column("temperature", role=Role.FIELD)
+
+    await store.create_bucket(SensorBucket)
+    bucket = store.get_bucket(SensorBucket)
+    assert bucket
+    assert bucket.to_dict()["meta"]["schema"] == {
+        "name": {"type": "string", "role": "measurement"},
+        "sensor_id": {"type": "string", "role": "tag"},
+        "temperature": {"type": "integer", "role": "field"},
+    }
+
+    await bucket.add(SensorBucket(name="climate", sensor_id="s-1", temperature=21))
+    await bucket.add(SensorBucket(name="climate", sensor_id=None, temperature=22))
+    result = await bucket.execute(select(SensorBucket).order_by(SensorBucket.temperature))
+    assert [r.sensor_id for r in result] == ["s-1", None]
+    with pytest.raises(ValueError):
+        await bucket.add(SensorBucket(name=None, sensor_id="s-1", temperature=21))
+    await store.delete_bucket(SensorBucket)
+
+
+@pytest.mark.asyncio
+async def test_add_typed_fields(store: Store):
+    class TypedBucket(Base):
+        sensor_id: Mapped[str] = mapped_column("sensor_id", role=Role.TAG)
+        temperature: Mapped[float] = mapped_column("temperature")
+        count: Mapped[int] = mapped_column("count")
+        total: Mapped[UInt] = mapped_column("total")
+        active: Mapped[bool] = mapped_column("active")
+
+    await store.create_bucket(TypedBucket)
+    bucket = store.get_bucket(TypedBucket)
+    assert bucket
+    await bucket.add(
+        TypedBucket(sensor_id="s-1", temperature=21.5, count=3, total=7, active=True)
+    )
+    await bucket.add(
+        TypedBucket(sensor_id="s-1", temperature=None, count=3, total=None, active=None)
+    )
+
+    with pytest.raises(TypeError):
+        await bucket.add(
+            TypedBucket(sensor_id="s-1", temperature=21.5, count=1.5, total=7, active=True)
+        )
+    with pytest.raises(TypeError):
+        await bucket.add(
+            TypedBucket(sensor_id="s-1", temperature="hot", count=3, total=7, active=True)
+        )
+    with pytest.raises(ValueError):
+        await bucket.add(
+            TypedBucket(sensor_id="s-1", temperature=21.5, count=3, total=-1, active=True)
+        )
+    with pytest.raises(ValueError):
+        await bucket.add(
+            TypedBucket(sensor_id="s-1", temperature=21.5, count=2**63, total=7, active=True)
+        )
+    await store.delete_bucket(TypedBucket)
+
+
+@pytest.mark.asyncio
+async def test_add_with_timestamp(store: Store):
+    class TimedBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(TimedBucket)
+    bucket = store.get_bucket(TimedBucket)
+    assert bucket
+    timestamp = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(minutes=30)
+    await bucket.add(TimedBucket(tag="test tag", field=10, time=timestamp))
+
+    result = await bucket.raw_query(
+        """from(bucket: "TimedBucket")
+               |> range(start: -1h)
+        """
+    )
+    assert [r.time for r in result] == [timestamp]
+    await store.delete_bucket(TimedBucket)
+
+
+def test_bad_precision():
+    engine = create_engine(
+        host="http://localhost:8086", token=token, org_id="", precision="m"
+    )
+    with pytest.raises(ValueError):
+        Store(bind=engine)
+
+
+@pytest.mark.asyncio
+async def test_add_all(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+    msmnts = [MockBucket(measurement=20, tag="test tag", field=i) for i in range(10)]
+
+    summary = await bucket.add_all(msmnts, max_points=4)
+    assert summary == {"points": 10, "requests": 3}
+
+
+@pytest.mark.asyncio
+async def test_buffered_writer(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+
+    async with bucket.writer(max_points=100, flush_interval=60) as writer:
+        for i in range(5):
+            writer.add(MockBucket(measurement=20, tag="test tag", field=i))
+        assert writer.pending() == 5
+        assert await writer.flush() == 5
+        assert writer.pending() == 0
+        writer.add(MockBucket(measurement=20, tag="test tag", field=5))
+
+    assert writer.pending() == 0
+    with pytest.raises(RuntimeError):
+        writer.add(MockBucket(measurement=20, tag="test tag", field=6))
+    for flush_interval in [0, -1, float("nan"), 1e20]:
+        with pytest.raises(ValueError):
+            bucket.writer(flush_interval=flush_interval)
+
+
+@pytest.mark.asyncio
+async def test_add_rejected(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+    # the bucket handle points to MockBucket, delete it to make writes fail
+    await store.delete_bucket(MockBucket)
+    with pytest.raises(RuntimeError):
+        await bucket.add(MockBucket(measurement=20, tag="test tag", field=1))
+    with pytest.raises(RuntimeError):
+        await bucket.add_all([MockBucket(measurement=20, tag="test tag", field=1)])
+
+
+@pytest.mark.asyncio
+async def test_buffered_writer_reports_failed_batches(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+    # the bucket handle points to MockBucket, delete it to make writes fail
+    await store.delete_bucket(MockBucket)
+
+    failed = []
+    writer = bucket.writer(on_error=lambda error, lines: failed.append(lines))
+    writer.add(MockBucket(measurement=20, tag="test tag", field=1))
+    assert await writer.close() == 0
+    assert failed == [["20,tag=test\\ tag field=1i"]]
+
+
 @pytest.mark.asyncio
 async def test_delete_bucket(store: Store):
     class DeleteMockBucket(Base):
-        measurement: Mapped[str] = mapped_column("measurement")
-        tag: Mapped[str] = mapped_column("tag")
-        field: Mapped[int] = mapped_column("field")
+        measurement: Mapped[str] = mapped_column("measurement", role=Role.MEASUREMENT)
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
 
     await store.create_bucket(DeleteMockBucket)
     await store.delete_bucket(DeleteMockBucket)
@@ -128,6 +309,109 @@ async def test_raw_query(store: Store):
     assert [r.dict() for r in result] == [msmnt.dict()]
 
 
+@pytest.mark.asyncio
+async def test_raw_query_results(store: Store):
+    await store.create_bucket(PivotBucket)
+    bucket = store.get_bucket(PivotBucket)
+    assert bucket
+    await bucket.add(PivotBucket(tag="a", low=1, high=5))
+    await bucket.add(PivotBucket(tag="a", low=3, high=7))
+
+    result = await bucket.raw_query(
+        """data = from(bucket: "PivotBucket") |> range(start: -1h)
+        data |> min() |> yield(name: "min")
+        data |> max() |> yield(name: "max")
+        """
+    )
+    assert [r.dict() for r in result.results["min"]] == [{"tag": "a", "low": 1, "high": 5}]
+    assert [r.dict() for r in result.results["max"]] == [{"tag": "a", "low": 3, "high": 7}]
+    assert len(result) == 2
+    assert sorted((t.result, t.group_key["_field"]) for t in result.tables) == [
+        ("max", "high"),
+        ("max", "low"),
+        ("min", "high"),
+        ("min", "low"),
+    ]
+    assert all(t.group_key["tag"] == "a" for t in result.tables)
+    await store.delete_bucket(PivotBucket)
+
+
+@pytest.mark.asyncio
+async def test_raw_query_typed_columns(store: Store):
+    class TypedQueryBucket(Base):
+        name: Mapped[str] = mapped_column("_measurement", role=Role.MEASUREMENT)
+        sensor_id: Mapped[str] = mapped_column("sensor_id", role=Role.TAG)
+        temperature: Mapped[float] = mapped_column("temperature")
+        count: Mapped[int] = mapped_column("count")
+        total: Mapped[UInt] = mapped_column("total")
+        active: Mapped[bool] = mapped_column("active")
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(TypedQueryBucket)
+    bucket = store.get_bucket(TypedQueryBucket)
+    assert bucket
+    timestamp = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(minutes=30)
+    await bucket.add(
+        TypedQueryBucket(
+            name="climate",
+            sensor_id="s-1",
+            temperature=21.0,
+            count=3,
+            total=7,
+            active=True,
+            time=timestamp,
+        )
+    )
+    await bucket.add(
+        TypedQueryBucket(
+            name="climate", sensor_id="s-1", temperature=22.5, time=timestamp + timedelta(minutes=1)
+        )
+    )
+
+    result = await bucket.raw_query(
+        """from(bucket: "TypedQueryBucket")
+               |> range(start: -1h)
+        """
+    )
+    by_time = {r.time: r.dict() for r in result}
+    assert by_time[timestamp]["temperature"] == 21.0
+    assert type(by_time[timestamp]["temperature"]) is float
+    assert by_time[timestamp]["count"] == 3
+    assert by_time[timestamp]["total"] == 7
+    assert by_time[timestamp]["active"] is True
+    assert by_time[timestamp + timedelta(minutes=1)]["count"] is None
+    await store.delete_bucket(TypedQueryBucket)
+
+
+@pytest.mark.asyncio
+async def test_raw_query_typed_measurement(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+    await bucket.add(MockBucket(measurement=20, tag="test tag", field=10))
+    await bucket.add(MockBucket(measurement="test measurement", tag="test tag", field=10))
+
+    result = await bucket.raw_query(
+        """from(bucket: "MockBucket")
+               |> range(start: -1h)
+        """
+    )
+    # measurements that don't convert to the declared type are read as written
+    assert {type(r.measurement): r.measurement for r in result} == {
+        int: 20,
+        str: "test measurement",
+    }
+
+
+@pytest.mark.asyncio
+async def test_raw_query_rejected(store: Store):
+    await store.create_bucket(MockBucket)
+    bucket = store.get_bucket(MockBucket)
+    assert bucket
+    with pytest.raises(RuntimeError):
+        await bucket.raw_query('from(bucket: "MockBucket") |> not_a_function()')
+
+
 @pytest.mark.asyncio
 async def test_query_empty_bucket(store: Store):
     await store.create_bucket(MockBucket)
@@ -152,3 +436,685 @@ async def test_query_bucket_with_no_filter(store: Store):
     stmt = select(MockBucket)
     result: list[MockBucket] = await bucket.execute(stmt)
     assert [r.dict() for r in result] == [msmnt.dict()]
+
+
+@pytest.mark.asyncio
+async def test_query_with_range(store: Store):
+    class RangeBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(RangeBucket)
+    bucket = store.get_bucket(RangeBucket)
+    assert bucket
+    timestamp = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(hours=3)
+    await bucket.add(RangeBucket(tag="test tag", field=10, time=timestamp))
+
+    assert await bucket.execute(select(RangeBucket)) == []
+    result = await bucket.execute(select(RangeBucket).range(start="-4h"))
+    assert [r.time for r in result] == [timestamp]
+    result = await bucket.execute(
+        select(RangeBucket).range(start=timestamp - timedelta(minutes=1), stop=timedelta(hours=-2))
+    )
+    assert [r.time for r in result] == [timestamp]
+    await store.delete_bucket(RangeBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_aggregate_window(store: Store):
+    class WindowBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(WindowBucket)
+    bucket = store.get_bucket(WindowBucket)
+    assert bucket
+    window = datetime.now(timezone.utc).replace(second=0, microsecond=0) - timedelta(hours=1)
+    await bucket.add(WindowBucket(tag="test tag", field=1, time=window + timedelta(seconds=10)))
+    await bucket.add(WindowBucket(tag="test tag", field=2, time=window + timedelta(seconds=20)))
+
+    stmt = select(WindowBucket).range(start="-2h")
+    result = await bucket.execute(stmt.aggregate_window(every="1m", fn="mean"))
+    assert [(r.field, r.time) for r in result] == [(1.5, window + timedelta(minutes=1))]
+    result = await bucket.execute(stmt.aggregate_window(every="1m", fn="count"))
+    assert [r.field for r in result] == [2]
+    await store.delete_bucket(WindowBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_group_by(store: Store):
+    class GroupBucket(Base):
+        host: Mapped[str] = mapped_column("host", role=Role.TAG)
+        region: Mapped[str] = mapped_column("region", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(GroupBucket)
+    bucket = store.get_bucket(GroupBucket)
+    assert bucket
+    window = datetime.now(timezone.utc).replace(second=0, microsecond=0) - timedelta(hours=1)
+    time = window + timedelta(seconds=10)
+    await bucket.add(GroupBucket(host="a", region="eu", field=1, time=time))
+    await bucket.add(GroupBucket(host="b", region="eu", field=2, time=time))
+    await bucket.add(GroupBucket(host="c", region="us", field=3, time=time))
+
+    stmt = select(GroupBucket).range(start="-2h").group_by(GroupBucket.region)
+    result = await bucket.execute(stmt.aggregate_window(every="1m", fn="sum"))
+    assert {key: [r.field for r in group] for key, group in result.items()} == {
+        ("eu",): [3],
+        ("us",): [3],
+    }
+    await store.delete_bucket(GroupBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_first_and_last(store: Store):
+    class OrderedBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(OrderedBucket)
+    bucket = store.get_bucket(OrderedBucket)
+    assert bucket
+    assert await bucket.execute(select(OrderedBucket).first()) is None
+    now = datetime.now(timezone.utc).replace(microsecond=0)
+    for i in range(5):
+        time = now - timedelta(minutes=10 - i)
+        await bucket.add(OrderedBucket(tag="test tag", field=i, time=time))
+    await bucket.add(OrderedBucket(tag="other tag", field=5, time=now - timedelta(minutes=3)))
+    await bucket.add(OrderedBucket(tag="other tag", field=-1, time=now - timedelta(minutes=20)))
+
+    stmt = select(OrderedBucket).where(OrderedBucket.tag == "test tag")
+    stmt = stmt.order_by(OrderedBucket.time.desc()).limit(3)
+    assert [r.field for r in await bucket.execute(stmt)] == [4, 3, 2]
+    stmt = select(OrderedBucket).where(OrderedBucket.tag == "test tag")
+    stmt = stmt.order_by(OrderedBucket.time).limit(2).offset(1)
+    assert [r.field for r in await bucket.execute(stmt)] == [1, 2]
+    assert (await bucket.execute(select(OrderedBucket).first())).field == -1
+    assert (await bucket.execute(select(OrderedBucket).last())).field == 5
+    stmt = select(OrderedBucket).group_by(OrderedBucket.tag).last()
+    assert {key: r.field for key, r in (await bucket.execute(stmt)).items()} == {
+        ("test tag",): 4,
+        ("other tag",): 5,
+    }
+    await store.delete_bucket(OrderedBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_columns(store: Store):
+    class WideBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        other: Mapped[str] = mapped_column("other", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        extra: Mapped[int] = mapped_column("extra", role=Role.FIELD)
+
+    await store.create_bucket(WideBucket)
+    bucket = store.get_bucket(WideBucket)
+    assert bucket
+    await bucket.add(WideBucket(tag="test tag", other="x", field=1, extra=2))
+
+    result = await bucket.execute(select(WideBucket.tag, WideBucket.field))
+    assert [r.dict() for r in result] == [{"tag": "test tag", "field": 1}]
+    await store.delete_bucket(WideBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_pivot(store: Store):
+    await store.create_bucket(PivotBucket)
+    bucket = store.get_bucket(PivotBucket)
+    assert bucket
+    await bucket.add(PivotBucket(tag="a", low=1, high=5))
+    await bucket.add(PivotBucket(tag="b", low=2, high=3))
+
+    stmt = select(PivotBucket).where(PivotBucket.high > 4)
+    assert [r.dict() for r in await bucket.execute(stmt)] == [
+        {"tag": "a", "low": 1, "high": 5}
+    ]
+    stmt = select(PivotBucket.low).where(PivotBucket.high > 4)
+    assert [r.dict() for r in await bucket.execute(stmt)] == [{"low": 1}]
+    await store.delete_bucket(PivotBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_transforms(store: Store):
+    class CounterBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(CounterBucket)
+    bucket = store.get_bucket(CounterBucket)
+    assert bucket
+    start = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(minutes=30)
+    await bucket.add_all(
+        [
+            CounterBucket(tag="test tag", field=value, time=start + timedelta(seconds=i * 10))
+            for i, value in enumerate([0, 10, 30, 5])
+        ]
+    )
+
+    stmt = select(CounterBucket)
+    result = await bucket.execute(stmt.non_negative_derivative("1s"))
+    assert [r.field for r in result] == [1.0, 2.0]
+    result = await bucket.execute(select(CounterBucket).difference())
+    assert [r.field for r in result] == [10, 20, -25]
+    result = await bucket.execute(select(CounterBucket).cumulative_sum())
+    assert [r.field for r in result] == [0, 10, 40, 45]
+    await store.delete_bucket(CounterBucket)
+
+
+@pytest.mark.asyncio
+async def test_query_elapsed(store: Store):
+    class ElapsedBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        elapsed: Mapped[int] = mapped_column("elapsed", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(ElapsedBucket)
+    bucket = store.get_bucket(ElapsedBucket)
+    assert bucket
+    start = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(minutes=30)
+    await bucket.add_all(
+        [
+            ElapsedBucket(tag="test tag", field=i, time=start + timedelta(seconds=seconds))
+            for i, seconds in enumerate([0, 10, 30])
+        ]
+    )
+
+    result = await bucket.execute(select(ElapsedBucket).elapsed())
+    assert [(r.field, r.elapsed) for r in result] == [(1, 10), (2, 20)]
+    result = await bucket.execute(select(ElapsedBucket).difference().elapsed("10s"))
+    assert [(r.field, r.elapsed) for r in result] == [(1, 2)]
+    await store.delete_bucket(ElapsedBucket)
+
+
+@pytest.mark.asyncio
+async def test_stream(store: Store):
+    class StreamBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("_time", role=Role.TIMESTAMP)
+
+    await store.create_bucket(StreamBucket)
+    bucket = store.get_bucket(StreamBucket)
+    assert bucket
+    start = datetime.now(timezone.utc).replace(microsecond=0) - timedelta(minutes=30)
+    await bucket.add_all(
+        [
+            StreamBucket(tag="test tag", field=i, time=start + timedelta(seconds=i))
+            for i in range(1000)
+        ]
+    )
+
+    stmt = select(StreamBucket)
+    assert [item.field async for item in bucket.stream(stmt)] == list(range(1000))
+    batches = [batch async for batch in bucket.stream(stmt).batches()]
+    assert sum(len(batch) for batch in batches) == 1000
+
+    async with bucket.stream(stmt) as stream:
+        async for item in stream:
+            assert item.field == 0
+            break
+    assert [item async for item in stream] == []
+    with pytest.raises(ValueError):
+        bucket.stream(select(StreamBucket).group_by(StreamBucket.tag))
+    with pytest.raises(ValueError):
+        bucket.stream(select(StreamBucket).last())
+    await store.delete_bucket(StreamBucket)
+
+
+@pytest.mark.asyncio
+async def test_execute_arrow(store: Store):
+    pyarrow = pytest.importorskip("pyarrow")
+    await store.create_bucket(PivotBucket)
+    bucket = store.get_bucket(PivotBucket)
+    assert bucket
+    await bucket.add(PivotBucket(tag="a", low=1, high=5))
+    await bucket.add(PivotBucket(tag="b", low=2, high=3))
+
+    result = await bucket.execute_arrow(select(PivotBucket))
+    assert result.num_rows == 2
+    table = result.to_pyarrow()
+    assert table.schema.field("low").type == pyarrow.int64()
+    assert table.sort_by("tag").to_pydict() == {
+        "tag": ["a", "b"],
+        "high": [5, 3],
+        "low": [1, 2],
+    }
+
+    result = await bucket.execute_arrow(select(PivotBucket.high, PivotBucket.tag))
+    assert result.column_names == ["high", "tag"]
+    await store.delete_bucket(PivotBucket)
+
+
+def test_duplicate_roles():
+    class TwoMeasurements(Base):
+        name: Mapped[str] = mapped_column("name", role=Role.MEASUREMENT)
+        kind: Mapped[str] = mapped_column("kind", role=Role.MEASUREMENT)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+
+    class TwoTimestamps(Base):
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        start: Mapped[datetime] = mapped_column("start", role=Role.TIMESTAMP)
+        stop: Mapped[datetime] = mapped_column("stop", role=Role.TIMESTAMP)
+
+    with pytest.raises(ValueError):
+        select(TwoMeasurements)
+    with pytest.raises(ValueError):
+        select(TwoTimestamps)
+
+
+def test_bad_range():
+    with pytest.raises(ValueError):
+        select(MockBucket).range(start="-1d", stop="-2d")
+    with pytest.raises(ValueError):
+        select(MockBucket).range(start="yesterday")
+    with pytest.raises(TypeError):
+        select(MockBucket).range(start=5)
+
+
+def test_select_to_flux():
+    stmt = select(MockBucket).where(MockBucket.field > 15, MockBucket.tag == "test tag")
+    stmt.range(start="-2h")
+    flux = stmt.to_flux()
+    assert flux == (
+        'from(bucket: "MockBucket")'
+        " |> range(start: -2h)"
+        ' |> filter(fn: (r) => r.tag == "test tag")'
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+        " |> filter(fn: (r) => r.field > 15)"
+    )
+    assert stmt.to_flux() == flux
+
+
+def test_select_escapes_literals():
+    class EscapeBucket(Base):
+        tag: Mapped[str] = mapped_column("my tag", role=Role.TAG)
+        field: Mapped[float] = mapped_column("field", role=Role.FIELD)
+
+    stmt = select(EscapeBucket).where(
+        EscapeBucket.tag == 'a"b\\c ${x}', EscapeBucket.field > 2.0
+    )
+    assert stmt.to_flux() == (
+        'from(bucket: "EscapeBucket")'
+        " |> range(start: -1h)"
+        ' |> filter(fn: (r) => r["my tag"] == "a\\"b\\\\c \\${x}")'
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+        " |> filter(fn: (r) => r.field > 2.0)"
+    )
+    with pytest.raises(TypeError):
+        select(EscapeBucket).where(EscapeBucket.field == [1])
+
+
+def test_select_typed_literals():
+    class LiteralBucket(Base):
+        name: Mapped[int] = mapped_column("_measurement", role=Role.MEASUREMENT)
+        temperature: Mapped[float] = mapped_column("temperature")
+        count: Mapped[int] = mapped_column("count")
+        total: Mapped[UInt] = mapped_column("total")
+        active: Mapped[bool] = mapped_column("active")
+
+    stmt = select(LiteralBucket).where(
+        LiteralBucket.name == 20,
+        LiteralBucket.temperature > -2,
+        LiteralBucket.count > 2**40,
+        LiteralBucket.total >= 3,
+        LiteralBucket.active == False,
+    )
+    assert stmt.to_flux().split(" |> filter(fn: (r) => ")[1:] == [
+        'r._measurement == "20")'
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")',
+        "r.temperature > -2.0)",
+        "r.count > 1099511627776)",
+        'r.total >= uint(v: "3"))',
+        "r.active == false)",
+    ]
+    with pytest.raises(TypeError):
+        select(LiteralBucket).where(LiteralBucket.count > 1.5)
+    with pytest.raises(TypeError):
+        select(LiteralBucket).where(LiteralBucket.active == 1)
+    with pytest.raises(ValueError):
+        select(LiteralBucket).where(LiteralBucket.total > -1)
+
+
+def test_select_measurement_and_timestamp():
+    class TimeBucket(Base):
+        measurement: Mapped[int] = mapped_column("measurement", role=Role.MEASUREMENT)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        time: Mapped[datetime] = mapped_column("time", role=Role.TIMESTAMP)
+
+    stmt = select(TimeBucket).where(
+        TimeBucket.measurement == 20,
+        TimeBucket.time > datetime(2024, 1, 1, tzinfo=timezone.utc),
+        TimeBucket.measurement.in_([1, 2]),
+        TimeBucket.time.is_not(None),
+    )
+    assert stmt.to_flux().split(" |> filter(fn: (r) => ")[1:] == [
+        'r._measurement == "20")',
+        "r._time > 2024-01-01T00:00:00Z)",
+        'contains(value: r._measurement, set: ["1", "2"]))',
+        "exists r._time)",
+    ]
+
+
+def test_select_boolean_combinators():
+    stmt = select(MockBucket).where(
+        ((MockBucket.field > 15) | (MockBucket.field < 5)) & ~(MockBucket.tag == "test tag")
+    )
+    assert stmt.to_flux().endswith(
+        ' |> filter(fn: (r) => (r.field > 15 or r.field < 5) and not (r.tag == "test tag"))'
+    )
+
+    stmt = select(MockBucket).where(
+        or_(and_(MockBucket.field > 15, MockBucket.tag == "a"), not_(MockBucket.field == 1))
+    )
+    assert stmt.to_flux().endswith(
+        ' |> filter(fn: (r) => r.field > 15 and r.tag == "a" or not (r.field == 1))'
+    )
+
+
+def test_select_in():
+    stmt = select(MockBucket).where(
+        MockBucket.tag.in_(["a", "b"]), MockBucket.field.not_in((1, 2))
+    )
+    assert stmt.to_flux().split(" |> filter(fn: (r) => ")[1:] == [
+        'contains(value: r.tag, set: ["a", "b"]))'
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")',
+        "not (contains(value: r.field, set: [1, 2])))",
+    ]
+    stmt = select(MockBucket).where(MockBucket.field.in_([]))
+    assert stmt.to_flux().endswith(" |> filter(fn: (r) => false)")
+    with pytest.raises(TypeError):
+        select(MockBucket).where(MockBucket.field.in_([1, "2"]))
+    with pytest.raises(TypeError):
+        MockBucket.tag.in_("ab")
+
+
+def test_select_string_matching():
+    stmt = select(MockBucket).where(
+        MockBucket.tag.matches(r"^s-\d+/a$"),
+        MockBucket.tag.not_matches("b"),
+        MockBucket.tag.startswith("s-") | MockBucket.tag.endswith("-1"),
+        MockBucket.tag.contains('"'),
+    )
+    assert stmt.to_flux() == (
+        'import "strings"\n'
+        'from(bucket: "MockBucket")'
+        " |> range(start: -1h)"
+        r" |> filter(fn: (r) => r.tag =~ /^s-\d+\/a$/)"
+        " |> filter(fn: (r) => r.tag !~ /b/)"
+        ' |> filter(fn: (r) => strings.hasPrefix(v: r.tag, prefix: "s-")'
+        ' or strings.hasSuffix(v: r.tag, suffix: "-1"))'
+        r' |> filter(fn: (r) => strings.containsStr(v: r.tag, substr: "\""))'
+    )
+    assert str(MockBucket.tag.contains('"${x}')) == r'tag.contains("\"\${x}")'
+    with pytest.raises(ValueError):
+        select(MockBucket).where(MockBucket.tag.matches("(unclosed"))
+    with pytest.raises(TypeError):
+        select(MockBucket).where(MockBucket.field.startswith("1"))
+
+
+def test_select_exists():
+    stmt = select(MockBucket).where(
+        MockBucket.tag.is_(None),
+        MockBucket.field.is_not(None),
+        MockBucket.field == None,
+        (MockBucket.tag != None) & (MockBucket.field > 1),
+    )
+    assert stmt.to_flux().split(" |> filter(fn: (r) => ")[1:] == [
+        "not exists r.tag)"
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")',
+        "exists r.field)",
+        "not exists r.field)",
+        "exists r.tag and r.field > 1)",
+    ]
+    with pytest.raises(TypeError):
+        MockBucket.field.is_(1)
+    with pytest.raises(TypeError):
+        select(MockBucket).where(MockBucket.field > None)
+
+
+def test_select_aggregate_window():
+    stmt = select(MockBucket).where(MockBucket.tag == "a").aggregate_window()
+    assert stmt.to_flux().endswith(
+        ' |> filter(fn: (r) => r.tag == "a")'
+        " |> aggregateWindow(every: 1m, fn: mean, createEmpty: false)"
+    )
+    stmt = select(MockBucket).aggregate_window(timedelta(minutes=5), "count", True)
+    assert stmt.to_flux().endswith(
+        " |> aggregateWindow(every: 5m, fn: count, createEmpty: true)"
+    )
+    with pytest.raises(ValueError):
+        select(MockBucket).aggregate_window(fn="avg")
+    with pytest.raises(ValueError):
+        select(MockBucket).aggregate_window(every="-1m")
+
+
+def test_select_aggregate_window_numeric_fields():
+    class StatusBucket(Base):
+        value: Mapped[float] = mapped_column("value", role=Role.FIELD)
+        status: Mapped[str] = mapped_column("status", role=Role.FIELD)
+
+    stmt = select(StatusBucket).aggregate_window(fn="mean")
+    assert stmt.to_flux() == (
+        'from(bucket: "StatusBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => contains(value: r._field, set: ["value"]))'
+        " |> aggregateWindow(every: 1m, fn: mean, createEmpty: false)"
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+    )
+    stmt = select(StatusBucket).aggregate_window(fn="count")
+    assert "contains(" not in stmt.to_flux()
+    with pytest.raises(TypeError):
+        select(StatusBucket.status).aggregate_window(fn="sum").to_flux()
+    with pytest.raises(TypeError):
+        select(StatusBucket.value, StatusBucket.status).aggregate_window().to_flux()
+
+
+def test_select_group_by():
+    stmt = select(MockBucket).group_by(MockBucket.tag, MockBucket.measurement)
+    assert stmt.aggregate_window(fn="sum").to_flux().endswith(
+        ' |> group(columns: ["tag", "_measurement", "_field"])'
+        " |> aggregateWindow(every: 1m, fn: sum, createEmpty: false)"
+    )
+    with pytest.raises(TypeError):
+        select(MockBucket).group_by(MockBucket.field)
+    with pytest.raises(ValueError):
+        select(MockBucket).group_by()
+
+
+def test_select_order_by_and_limit():
+    stmt = select(MockBucket).order_by(MockBucket.field.desc()).limit(10).offset(5)
+    assert stmt.to_flux().endswith(
+        ' |> sort(columns: ["field"], desc: true) |> limit(n: 10, offset: 5)'
+    )
+    stmt = select(MockBucket).order_by(MockBucket.tag, MockBucket.measurement)
+    assert stmt.to_flux().endswith(
+        ' |> sort(columns: ["tag", "_measurement"], desc: false)'
+    )
+    assert select(MockBucket).first().to_flux().endswith(
+        ' |> group() |> sort(columns: ["_time"], desc: false) |> limit(n: 1)'
+    )
+    assert select(MockBucket).last().to_flux().endswith(
+        ' |> group() |> sort(columns: ["_time"], desc: true) |> limit(n: 1)'
+    )
+    assert select(MockBucket).last().last().to_flux().endswith(
+        ' |> group() |> sort(columns: ["_time"], desc: true) |> limit(n: 1)'
+    )
+    stmt = select(MockBucket).last().order_by(MockBucket.field)
+    assert stmt.to_flux().endswith(
+        ' |> group() |> sort(columns: ["field"], desc: true) |> limit(n: 1)'
+    )
+    stmt = select(MockBucket).order_by(MockBucket.field.desc()).last()
+    assert stmt.to_flux().endswith(
+        ' |> group() |> sort(columns: ["field"], desc: false) |> limit(n: 1)'
+    )
+    assert select(MockBucket).group_by(MockBucket.tag).last().to_flux().endswith(
+        ' |> group(columns: ["tag", "_field"])'
+        ' |> sort(columns: ["_time"], desc: true) |> limit(n: 1)'
+    )
+    with pytest.raises(ValueError):
+        select(MockBucket).order_by(MockBucket.tag.asc(), MockBucket.field.desc())
+    with pytest.raises(ValueError):
+        select(MockBucket).limit(-1)
+    with pytest.raises(ValueError):
+        select(MockBucket).offset(5).to_flux()
+
+
+def test_select_columns():
+    stmt = select(MockBucket.tag, MockBucket.field).where(MockBucket.tag == "a")
+    assert stmt.to_flux() == (
+        'from(bucket: "MockBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => contains(value: r._field, set: ["field"]))'
+        ' |> filter(fn: (r) => r.tag == "a")'
+        ' |> keep(columns: ["_time", "_field", "_value", "tag"])'
+    )
+    assert select(MockBucket.measurement).to_flux().endswith(
+        ' |> keep(columns: ["_time", "_field", "_value", "_measurement"])'
+    )
+    with pytest.raises(TypeError):
+        select(MockBucket, MockBucket.tag)
+
+
+class PivotBucket(Base):
+    tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+    low: Mapped[int] = mapped_column("low", role=Role.FIELD)
+    high: Mapped[int] = mapped_column("high", role=Role.FIELD)
+
+
+def test_select_pivot():
+    pivot = (
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+    )
+    stmt = select(PivotBucket).where(PivotBucket.high > 1).group_by(PivotBucket.tag)
+    assert stmt.to_flux() == (
+        'from(bucket: "PivotBucket") |> range(start: -1h)'
+        + pivot
+        + " |> filter(fn: (r) => r.high > 1)"
+        + ' |> group(columns: ["tag"])'
+    )
+    stmt = select(PivotBucket).where(PivotBucket.tag == "a").group_by(PivotBucket.tag)
+    assert stmt.aggregate_window(fn="max").to_flux() == (
+        'from(bucket: "PivotBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => r.tag == "a")'
+        ' |> group(columns: ["tag", "_field"])'
+        " |> aggregateWindow(every: 1m, fn: max, createEmpty: false)"
+        + pivot
+    )
+    stmt = select(MockBucket).order_by(MockBucket.field)
+    assert stmt.to_flux().endswith(pivot + ' |> sort(columns: ["field"], desc: false)')
+    with pytest.raises(ValueError):
+        select(PivotBucket).where(PivotBucket.high > 1).aggregate_window(fn="max").to_flux()
+    stmt = select(PivotBucket.high).where((PivotBucket.high > 1) & (PivotBucket.tag == "a"))
+    assert stmt.aggregate_window(fn="max").to_flux() == (
+        'from(bucket: "PivotBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => contains(value: r._field, set: ["high"]))'
+        ' |> filter(fn: (r) => r._field == "high" and r._value > 1 and r.tag == "a")'
+        " |> aggregateWindow(every: 1m, fn: max, createEmpty: false)"
+        + pivot
+        + ' |> keep(columns: ["_time", "high"])'
+    )
+    assert select(PivotBucket.low, PivotBucket.tag).to_flux().endswith(
+        pivot + ' |> keep(columns: ["_time", "low", "tag"])'
+    )
+    stmt = select(PivotBucket.low).where(PivotBucket.high > 4)
+    assert stmt.to_flux() == (
+        'from(bucket: "PivotBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => contains(value: r._field, set: ["low", "high"]))'
+        + pivot
+        + " |> filter(fn: (r) => r.high > 4)"
+        + ' |> keep(columns: ["_time", "low"])'
+    )
+    assert pivot not in select(MockBucket).where(MockBucket.tag == "a").to_flux()
+
+
+def test_select_transforms():
+    class RateBucket(Base):
+        tag: Mapped[str] = mapped_column("tag", role=Role.TAG)
+        field: Mapped[int] = mapped_column("field", role=Role.FIELD)
+        dt: Mapped[int] = mapped_column("dt", role=Role.FIELD)
+
+    stmt = select(MockBucket).where(MockBucket.tag == "a").non_negative_derivative("1m")
+    assert stmt.to_flux().endswith(
+        ' |> filter(fn: (r) => r.tag == "a")'
+        " |> derivative(unit: 1m, nonNegative: true)"
+    )
+    stmt = (
+        select(RateBucket)
+        .aggregate_window(fn="max")
+        .difference()
+        .moving_average(3)
+        .exponential_moving_average(2)
+        .cumulative_sum()
+        .elapsed(timedelta(seconds=1), "dt")
+    )
+    assert stmt.to_flux().endswith(
+        " |> aggregateWindow(every: 1m, fn: max, createEmpty: false)"
+        " |> difference(nonNegative: false)"
+        " |> movingAverage(n: 3)"
+        " |> exponentialMovingAverage(n: 2)"
+        " |> cumulativeSum()"
+        ' |> elapsed(unit: 1s, columnName: "dt")'
+    )
+    stmt = select(PivotBucket).where(PivotBucket.tag == "a").derivative().moving_average(3)
+    assert stmt.to_flux().endswith(
+        " |> derivative(unit: 1s, nonNegative: false)"
+        " |> movingAverage(n: 3)"
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+    )
+    stmt = select(RateBucket.tag, RateBucket.dt).elapsed(column_name="dt")
+    assert stmt.to_flux().endswith(
+        ' |> elapsed(unit: 1s, columnName: "dt")'
+        ' |> keep(columns: ["_time", "_field", "_value", "tag", "dt"])'
+    )
+    stmt = select(PivotBucket).where(PivotBucket.tag == "a").elapsed(column_name="low")
+    assert stmt.to_flux() == (
+        'from(bucket: "PivotBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => r._field != "low")'
+        ' |> filter(fn: (r) => r.tag == "a")'
+        ' |> elapsed(unit: 1s, columnName: "low")'
+    )
+    stmt = select(PivotBucket).where(PivotBucket.high > 1).derivative()
+    assert stmt.to_flux().endswith(
+        " |> filter(fn: (r) => r.high > 1)"
+        ' |> derivative(unit: 1s, nonNegative: false, columns: ["high", "low"])'
+    )
+    class StatusBucket(Base):
+        high: Mapped[int] = mapped_column("high", role=Role.FIELD)
+        low: Mapped[int] = mapped_column("low", role=Role.FIELD)
+        status: Mapped[str] = mapped_column("status", role=Role.FIELD)
+
+    stmt = select(StatusBucket).where(StatusBucket.high > 1).derivative()
+    assert stmt.to_flux().endswith(
+        ' |> derivative(unit: 1s, nonNegative: false, columns: ["high", "low"])'
+    )
+    stmt = select(StatusBucket).cumulative_sum()
+    assert stmt.to_flux() == (
+        'from(bucket: "StatusBucket") |> range(start: -1h)'
+        ' |> filter(fn: (r) => contains(value: r._field, set: ["high", "low"]))'
+        " |> cumulativeSum()"
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+    )
+    with pytest.raises(TypeError):
+        select(StatusBucket.status).where(StatusBucket.high > 1).difference().to_flux()
+    stmt = select(MockBucket).where(MockBucket.field > 1).moving_average(3)
+    assert stmt.to_flux().endswith(
+        ' |> filter(fn: (r) => r._field == "field" and r._value > 1)'
+        " |> movingAverage(n: 3)"
+        ' |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")'
+    )
+    with pytest.raises(ValueError):
+        select(PivotBucket).where(PivotBucket.low > 1).moving_average(3).to_flux()
+    with pytest.raises(ValueError):
+        select(MockBucket).moving_average(0)
+    with pytest.raises(ValueError):
+        select(MockBucket).derivative("-1s")
+    with pytest.raises(ValueError):
+        select(MockBucket).elapsed()
+    with pytest.raises(ValueError):
+        select(MockBucket).elapsed(column_name="tag")
+    with pytest.raises(ValueError):
+        select(RateBucket).where(RateBucket.dt > 1).elapsed(column_name="dt").to_flux()